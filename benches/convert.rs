@@ -0,0 +1,50 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use img_to_video::convert::{
+    convert_rgb_to_yuv420_filtered, ColorSpace, Downsampling, Matrix, Range,
+};
+
+/// Returns a deterministic pseudo-random RGB image.
+fn noise(width: u32, height: u32, bytes_per_pixel: usize) -> Vec<u8> {
+    let mut state = width ^ height;
+    (0..width as usize * height as usize * bytes_per_pixel)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+fn rgb_to_yuv420(c: &mut Criterion) {
+    let color_space = ColorSpace {
+        matrix: Matrix::Bt709,
+        range: Range::Limited,
+    };
+    let mut group = c.benchmark_group("rgb_to_yuv420");
+    group.sample_size(20);
+    for (name, width, height) in [("1080p", 1920, 1080), ("4k", 3840, 2160)] {
+        group.throughput(Throughput::Elements(u64::from(width * height)));
+        for bytes_per_pixel in [3, 4] {
+            let rgb = noise(width, height, bytes_per_pixel);
+            for filter in [Downsampling::Box, Downsampling::Triangle] {
+                let id = format!("{}/{}bpp/{:?}", name, bytes_per_pixel, filter);
+                group.bench_with_input(BenchmarkId::from_parameter(id), &rgb, |b, rgb| {
+                    b.iter(|| {
+                        convert_rgb_to_yuv420_filtered(
+                            width,
+                            height,
+                            black_box(rgb),
+                            bytes_per_pixel,
+                            filter,
+                            color_space,
+                        )
+                    })
+                });
+            }
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, rgb_to_yuv420);
+criterion_main!(benches);
@@ -0,0 +1,331 @@
+//! Conversion to YUV with more than 8 bits per sample.
+//!
+//! The components are converted in floating point from their full precision,
+//! so 16 bits and floating point images keep their gradations. The chroma is
+//! downsampled with the same filters and siting as the 8 bits conversion.
+
+use super::{ColorSpace, Downsampling, Matrix, PixelFormat, Pixels, Range};
+
+/// Convert pixels in any [Pixels] layout to YUV in `format` and `color_space`,
+/// with `bit_depth` bits per sample. The chroma is downsampled with `filter` for
+/// the formats which need it.
+///
+/// # Arguments
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `pixels` - The pixels of the image.
+/// * `format` - The layout of the planes.
+/// * `bit_depth` - The number of bits per sample, from 9 to 16.
+/// * `filter` - The chroma downsampling filter.
+/// * `color_space` - The matrix and range of the YUV values.
+///
+/// # Returns
+/// A buffer array of [PixelFormat::frame_size] samples.
+///
+/// # Panics
+/// If `bit_depth` is not between 9 and 16.
+///
+/// # Example
+/// ```
+/// use img_to_video::convert::{convert_to_yuv_high, ColorSpace, Downsampling, PixelFormat, Pixels};
+/// let white = [65535; 12];
+/// let yuv = convert_to_yuv_high(2, 2, Pixels::Rgb16(&white), PixelFormat::I420, 10, Downsampling::Box, ColorSpace::default());
+/// assert_eq!(yuv, vec![1023, 1023, 1023, 1023, 512, 512]);
+/// ```
+pub fn convert_to_yuv_high(
+    width: u32,
+    height: u32,
+    pixels: Pixels,
+    format: PixelFormat,
+    bit_depth: u32,
+    filter: Downsampling,
+    color_space: ColorSpace,
+) -> Vec<u16> {
+    assert!(
+        (9..=16).contains(&bit_depth),
+        "unsupported bit depth: {}",
+        bit_depth
+    );
+    let (chroma_width, chroma_height) = format.chroma_size(width, height);
+    let (y_shift, chroma_width, chroma_height) = (
+        format.chroma_shift().1,
+        chroma_width as usize,
+        chroma_height as usize,
+    );
+    let (width, height) = (width as usize, height as usize);
+    let frame_size = width * height;
+    let mut yuv: Vec<u16> = vec![0; frame_size + 2 * chroma_width * chroma_height];
+    let (luma, chroma) = yuv.split_at_mut(frame_size);
+    let (u_plane, v_plane) = chroma.split_at_mut(chroma_width * chroma_height);
+    assert!(
+        pixels.len() >= frame_size * pixels.channels(),
+        "{} components for a {}x{} image",
+        pixels.len(),
+        width,
+        height
+    );
+
+    let taps = filter.taps();
+    let vertical_taps: &[(isize, i32)] = match y_shift {
+        0 => &[(0, 1)],
+        _ => taps,
+    };
+    let weight: i32 = vertical_taps.iter().map(|(_, w)| w).sum();
+    let scale = Scale::new(color_space.range, bit_depth);
+    let mut rows = FilteredRows::new(pixels, width, format, color_space.matrix, scale, taps);
+    let (mut u_sum, mut v_sum) = (vec![0.0; chroma_width], vec![0.0; chroma_width]);
+    for cy in 0..chroma_height {
+        u_sum.fill(0.0);
+        v_sum.fill(0.0);
+        for &(dy, wy) in vertical_taps {
+            let row = (cy << y_shift).saturating_add_signed(dy).min(height - 1);
+            let (u_row, v_row) = rows.get(row, luma);
+            for (sum, value) in u_sum.iter_mut().zip(u_row) {
+                *sum += wy as f32 * value;
+            }
+            for (sum, value) in v_sum.iter_mut().zip(v_row) {
+                *sum += wy as f32 * value;
+            }
+        }
+        let chroma_row = cy * chroma_width..(cy + 1) * chroma_width;
+        for (out, &sum) in u_plane[chroma_row.clone()].iter_mut().zip(&u_sum) {
+            *out = scale.chroma(sum / weight as f32);
+        }
+        for (out, &sum) in v_plane[chroma_row].iter_mut().zip(&v_sum) {
+            *out = scale.chroma(sum / weight as f32);
+        }
+    }
+    yuv
+}
+
+/// Luma weights of the red and blue components of a matrix.
+fn luma_weights(matrix: Matrix) -> (f32, f32) {
+    match matrix {
+        Matrix::Bt601 => (0.299, 0.114),
+        Matrix::Bt709 => (0.2126, 0.0722),
+        Matrix::Bt2020 => (0.2627, 0.0593),
+    }
+}
+
+/// Scale of the luma and chroma values for a range and a bit depth.
+#[derive(Clone, Copy)]
+struct Scale {
+    luma: f32,
+    luma_offset: f32,
+    chroma: f32,
+    chroma_offset: f32,
+    max: f32,
+}
+
+impl Scale {
+    fn new(range: Range, bit_depth: u32) -> Self {
+        let max = ((1 << bit_depth) - 1) as f32;
+        let chroma_offset = (1 << (bit_depth - 1)) as f32;
+        match range {
+            Range::Full => Scale {
+                luma: max,
+                luma_offset: 0.0,
+                chroma: max,
+                chroma_offset,
+                max,
+            },
+            Range::Limited => {
+                let step = (1 << (bit_depth - 8)) as f32;
+                Scale {
+                    luma: 219.0 * step,
+                    luma_offset: 16.0 * step,
+                    chroma: 224.0 * step,
+                    chroma_offset,
+                    max,
+                }
+            }
+        }
+    }
+
+    /// Returns the sample of a luma in `0.0..=1.0`.
+    fn luma(self, luma: f32) -> u16 {
+        (luma * self.luma + self.luma_offset)
+            .round()
+            .clamp(0.0, self.max) as u16
+    }
+
+    /// Returns the sample of a chroma in `-0.5..=0.5`.
+    fn chroma(self, chroma: f32) -> u16 {
+        (chroma * self.chroma + self.chroma_offset)
+            .round()
+            .clamp(0.0, self.max) as u16
+    }
+}
+
+/// Horizontally filtered chroma of the last rows of an image, kept by their
+/// index modulo 4 like the rows of the 8 bits conversion.
+struct FilteredRows<'a> {
+    pixels: Pixels<'a>,
+    width: usize,
+    /// Luma weights of the red and blue components.
+    weights: (f32, f32),
+    scale: Scale,
+    /// Horizontal taps, or `None` if the chroma has the width of the image.
+    taps: Option<&'static [(isize, i32)]>,
+    indices: [Option<usize>; 4],
+    u_rows: [Vec<f32>; 4],
+    v_rows: [Vec<f32>; 4],
+    u_pixels: Vec<f32>,
+    v_pixels: Vec<f32>,
+    rgb_row: Vec<f32>,
+}
+
+impl<'a> FilteredRows<'a> {
+    fn new(
+        pixels: Pixels<'a>,
+        width: usize,
+        format: PixelFormat,
+        matrix: Matrix,
+        scale: Scale,
+        taps: &'static [(isize, i32)],
+    ) -> Self {
+        let chroma_width = format.chroma_size(width as u32, 1).0 as usize;
+        FilteredRows {
+            pixels,
+            width,
+            weights: luma_weights(matrix),
+            scale,
+            taps: (format.chroma_shift().0 == 1).then_some(taps),
+            indices: [None; 4],
+            u_rows: std::array::from_fn(|_| vec![0.0; chroma_width]),
+            v_rows: std::array::from_fn(|_| vec![0.0; chroma_width]),
+            u_pixels: vec![0.0; width],
+            v_pixels: vec![0.0; width],
+            rgb_row: Vec::with_capacity(width * 3),
+        }
+    }
+
+    /// Returns the filtered chroma of a row, writing its luma into `luma` if it
+    /// was not computed yet.
+    fn get(&mut self, row: usize, luma: &mut [u16]) -> (&[f32], &[f32]) {
+        let slot = row % 4;
+        if self.indices[slot] != Some(row) {
+            self.convert_row(row, luma);
+            for (pixels, filtered) in [
+                (&self.u_pixels, &mut self.u_rows[slot]),
+                (&self.v_pixels, &mut self.v_rows[slot]),
+            ] {
+                match self.taps {
+                    Some(taps) => filter_row(pixels, taps, filtered),
+                    None => filtered.copy_from_slice(pixels),
+                }
+            }
+            self.indices[slot] = Some(row);
+        }
+        (&self.u_rows[slot], &self.v_rows[slot])
+    }
+
+    fn convert_row(&mut self, row: usize, luma: &mut [u16]) {
+        let width = self.width;
+        let (kr, kb) = self.weights;
+        let kg = 1.0 - kr - kb;
+        self.pixels.row_f32(row, width, &mut self.rgb_row);
+        let luma = &mut luma[row * width..(row + 1) * width];
+        for (index, rgb) in self.rgb_row.chunks_exact(3).enumerate() {
+            let (r, g, b) = (rgb[0], rgb[1], rgb[2]);
+            let y = kr * r + kg * g + kb * b;
+            luma[index] = self.scale.luma(y);
+            self.u_pixels[index] = (b - y) / (2.0 * (1.0 - kb));
+            self.v_pixels[index] = (r - y) / (2.0 * (1.0 - kr));
+        }
+    }
+}
+
+/// Filter the chroma of a row at every other pixel, repeating the border pixels.
+fn filter_row(pixels: &[f32], taps: &[(isize, i32)], filtered: &mut [f32]) {
+    let weight: i32 = taps.iter().map(|(_, w)| w).sum();
+    let last = pixels.len() - 1;
+    for (x, out) in filtered.iter_mut().enumerate() {
+        let sum: f32 = taps
+            .iter()
+            .map(|&(dx, w)| w as f32 * pixels[(2 * x).saturating_add_signed(dx).min(last)])
+            .sum();
+        *out = sum / weight as f32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::convert_to_yuv;
+
+    fn color_space() -> ColorSpace {
+        ColorSpace {
+            matrix: Matrix::Bt709,
+            range: Range::Limited,
+        }
+    }
+
+    #[test]
+    fn matches_8_bits_conversion() {
+        let (width, height) = (9, 7);
+        let rgb: Vec<u8> = (0..width * height * 3)
+            .map(|i| (i * 53 % 256) as u8)
+            .collect();
+        for format in [PixelFormat::I420, PixelFormat::I422, PixelFormat::I444] {
+            for filter in [Downsampling::Box, Downsampling::Triangle] {
+                let pixels = Pixels::Rgb8(&rgb);
+                let expected = convert_to_yuv(width, height, pixels, format, filter, color_space());
+                let high =
+                    convert_to_yuv_high(width, height, pixels, format, 10, filter, color_space());
+                assert_eq!(high.len(), format.frame_size(width, height));
+                // The 8 bits conversion uses rounded coefficients, within a step.
+                for (index, (&high, &low)) in high.iter().zip(&expected).enumerate() {
+                    let difference = (high as i32 - 4 * low as i32).abs();
+                    assert!(difference <= 6, "{:?} sample {}", format, index);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        let pixels = [0, 0, 0, 65535, 65535, 65535, 65535, 0, 0];
+        let convert = |range, bit_depth| {
+            let color_space = ColorSpace {
+                matrix: Matrix::Bt709,
+                range,
+            };
+            let pixels = Pixels::Rgb16(&pixels);
+            let format = PixelFormat::I444;
+            convert_to_yuv_high(
+                3,
+                1,
+                pixels,
+                format,
+                bit_depth,
+                Downsampling::Box,
+                color_space,
+            )
+        };
+        assert_eq!(
+            convert(Range::Limited, 10),
+            vec![64, 940, 250, 512, 512, 409, 512, 512, 960]
+        );
+        assert_eq!(
+            convert(Range::Full, 12),
+            vec![0, 4095, 871, 2048, 2048, 1579, 2048, 2048, 4095]
+        );
+    }
+
+    #[test]
+    fn gradients_are_kept() {
+        // Neighbouring 16 bits grays which fall on the same 8 bits value.
+        let luma: Vec<u16> = (0..4).map(|i| 32768 + 64 * i).collect();
+        let yuv = convert_to_yuv_high(
+            4,
+            1,
+            Pixels::Luma16(&luma),
+            PixelFormat::I444,
+            12,
+            Downsampling::Box,
+            ColorSpace::default(),
+        );
+        assert_eq!(yuv[..4], [2048, 2052, 2056, 2060]);
+    }
+}
@@ -0,0 +1,1001 @@
+//! Module that contains the conversion functions.
+
+mod high;
+mod pixels;
+mod simd;
+
+pub use high::convert_to_yuv_high;
+pub use pixels::Pixels;
+
+/// Represent a YUV420 Pixel.
+pub struct YUVPixel {
+    pub y: u8,
+    pub u: u8,
+    pub v: u8,
+}
+
+/// Convert an RGB pixel to YUV420.
+/// # Arguments
+/// * `r` - Red component of the pixel.
+/// * `g` - Green component of the pixel.
+/// * `b` - Blue component of the pixel.
+/// # Returns
+/// A YUVPixel.
+/// # Example
+/// ```
+/// use img_to_video::convert::convert_rgb_to_yuv420_pixel;
+/// let pixel = convert_rgb_to_yuv420_pixel(255.0, 0.0, 0.0);
+/// assert_eq!(pixel.y, 77);
+/// assert_eq!(pixel.u, 85);
+/// assert_eq!(pixel.v, 255);
+/// ```
+pub fn convert_rgb_to_yuv420_pixel(r: f32, g: f32, b: f32) -> YUVPixel {
+    YUVPixel {
+        y: ((77_f32 * r + 150_f32 * g + 29_f32 * b + 128.0) as i32 >> 8).clamp(0, 255) as u8,
+        u: (((-43_f32 * r - 84_f32 * g + 127_f32 * b + 128.0) as i32 >> 8) + 128).clamp(0, 255)
+            as u8,
+        v: (((127_f32 * r - 106_f32 * g - 21_f32 * b + 128.0) as i32 >> 8) + 128).clamp(0, 255)
+            as u8,
+    }
+}
+
+/// Returns the size of a YUV420 buffer.
+/// The chroma planes have half the width and height of the image, rounded up.
+///
+/// # Example
+/// ```
+/// use img_to_video::convert::yuv420_size;
+/// assert_eq!(yuv420_size(4, 2), 8 + 2 * 2);
+/// assert_eq!(yuv420_size(3, 3), 9 + 2 * 4);
+/// ```
+pub fn yuv420_size(width: u32, height: u32) -> usize {
+    let (width, height) = (width as usize, height as usize);
+    width * height + 2 * width.div_ceil(2) * height.div_ceil(2)
+}
+
+/// Filters used to downsample the chroma planes.
+///
+/// Both filters site the chroma at the centre of each 2x2 block of pixels.
+/// Pixels past the borders of the image repeat the border pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Downsampling {
+    /// Average of the 2x2 block.
+    Box,
+    /// Weighted average of the 4x4 pixels around the block, with the weights
+    /// 1, 3, 3, 1 on each axis. It is softer than the box filter, with less aliasing.
+    Triangle,
+}
+
+impl Downsampling {
+    /// Returns the offsets from the top left pixel of a block and the weights of the taps.
+    fn taps(self) -> &'static [(isize, i32)] {
+        match self {
+            Downsampling::Box => &[(0, 1), (1, 1)],
+            Downsampling::Triangle => &[(-1, 1), (0, 3), (1, 3), (2, 1)],
+        }
+    }
+}
+
+/// Layouts of the planes of a converted frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Chroma planes with half the width and height of the image, rounded up.
+    #[default]
+    I420,
+    /// Chroma planes with half the width of the image, rounded up.
+    I422,
+    /// Chroma planes with the size of the image.
+    I444,
+}
+
+impl PixelFormat {
+    /// Returns the base 2 logarithm of the horizontal and vertical chroma subsampling.
+    pub fn chroma_shift(self) -> (u32, u32) {
+        match self {
+            PixelFormat::I420 => (1, 1),
+            PixelFormat::I422 => (1, 0),
+            PixelFormat::I444 => (0, 0),
+        }
+    }
+
+    /// Returns the width and height of the chroma planes of an image.
+    pub fn chroma_size(self, width: u32, height: u32) -> (u32, u32) {
+        let (x_shift, y_shift) = self.chroma_shift();
+        (width.div_ceil(1 << x_shift), height.div_ceil(1 << y_shift))
+    }
+
+    /// Returns the number of samples of a frame in this format, which is its
+    /// size in bytes with 8 bits per sample.
+    ///
+    /// # Example
+    /// ```
+    /// use img_to_video::convert::PixelFormat;
+    /// assert_eq!(PixelFormat::I420.frame_size(4, 2), 8 + 2 * 2);
+    /// assert_eq!(PixelFormat::I422.frame_size(3, 2), 6 + 2 * 4);
+    /// assert_eq!(PixelFormat::I444.frame_size(4, 2), 3 * 8);
+    /// ```
+    pub fn frame_size(self, width: u32, height: u32) -> usize {
+        let (chroma_width, chroma_height) = self.chroma_size(width, height);
+        width as usize * height as usize + 2 * chroma_width as usize * chroma_height as usize
+    }
+}
+
+/// Matrices used to compute the luma and chroma from RGB.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Matrix {
+    /// ITU-R BT.601, for standard definition.
+    #[default]
+    Bt601,
+    /// ITU-R BT.709, for high definition.
+    Bt709,
+    /// ITU-R BT.2020, non-constant luminance.
+    Bt2020,
+}
+
+/// Ranges of the YUV values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Range {
+    /// Luma from 16 to 235 and chroma from 16 to 240, as expected by most players.
+    Limited,
+    /// Luma and chroma from 0 to 255.
+    #[default]
+    Full,
+}
+
+/// Colour space of the converted frames.
+///
+/// The default is full range BT.601, the colour space of [convert_rgb_to_yuv420_pixel].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ColorSpace {
+    pub matrix: Matrix,
+    pub range: Range,
+}
+
+/// Fixed point coefficients of a colour space, in 1/256.
+#[derive(Debug, Clone, Copy)]
+struct Coefficients {
+    y: [i32; 3],
+    u: [i32; 3],
+    v: [i32; 3],
+    y_offset: i32,
+}
+
+impl ColorSpace {
+    /// Returns the coefficients of the colour space.
+    /// Luma coefficients sum to the luma scale, so white is exactly 255 or 235,
+    /// and chroma coefficients sum to zero, so grays have a neutral chroma.
+    fn coefficients(self) -> Coefficients {
+        let (y, u, v) = match (self.matrix, self.range) {
+            (Matrix::Bt601, Range::Full) => ([77, 150, 29], [-43, -84, 127], [127, -106, -21]),
+            (Matrix::Bt601, Range::Limited) => ([66, 129, 25], [-38, -74, 112], [112, -94, -18]),
+            (Matrix::Bt709, Range::Full) => ([54, 183, 19], [-29, -98, 127], [127, -115, -12]),
+            (Matrix::Bt709, Range::Limited) => ([47, 157, 16], [-26, -86, 112], [112, -102, -10]),
+            (Matrix::Bt2020, Range::Full) => ([67, 174, 15], [-35, -92, 127], [127, -117, -10]),
+            (Matrix::Bt2020, Range::Limited) => ([58, 149, 13], [-31, -81, 112], [112, -103, -9]),
+        };
+        let y_offset = match self.range {
+            Range::Limited => 16,
+            Range::Full => 0,
+        };
+        Coefficients { y, u, v, y_offset }
+    }
+}
+
+impl Coefficients {
+    /// Returns the coefficients for pixels in blue, green, red order.
+    fn bgr(self) -> Self {
+        let swap = |[r, g, b]: [i32; 3]| [b, g, r];
+        Coefficients {
+            y: swap(self.y),
+            u: swap(self.u),
+            v: swap(self.v),
+            y_offset: self.y_offset,
+        }
+    }
+}
+
+/// Convert an RGB buffer array to full range BT.601 YUV420, with box-filtered chroma.
+///
+/// # Arguments
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `rgb` - The RGB buffer array.
+/// * `bytes_per_pixel` - The number of bytes per pixel.
+///
+/// # Returns
+/// A YUV420 buffer array, of [yuv420_size] bytes.
+///
+/// # Example
+/// ```
+/// use img_to_video::convert::convert_rgb_to_yuv420;
+/// let rgb = vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+/// let yuv = convert_rgb_to_yuv420(2, 2, &rgb, 3);
+/// assert_eq!(yuv, vec![0, 0, 0, 0, 128, 128]);
+/// ```
+pub fn convert_rgb_to_yuv420(
+    width: u32,
+    height: u32,
+    rgb: &[u8],
+    bytes_per_pixel: usize,
+) -> Vec<u8> {
+    convert_rgb_to_yuv420_filtered(
+        width,
+        height,
+        rgb,
+        bytes_per_pixel,
+        Downsampling::Box,
+        ColorSpace::default(),
+    )
+}
+
+/// Convert an RGB buffer array to YUV420 in `color_space`, downsampling the chroma with `filter`.
+///
+/// # Arguments
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `rgb` - The RGB buffer array.
+/// * `bytes_per_pixel` - The number of bytes per pixel, 3 for RGB or 4 for RGBA.
+/// * `filter` - The chroma downsampling filter.
+/// * `color_space` - The matrix and range of the YUV values.
+///
+/// # Returns
+/// A YUV420 buffer array, of [yuv420_size] bytes.
+///
+/// # Panics
+/// If `bytes_per_pixel` is neither 3 nor 4.
+///
+/// # Example
+/// ```
+/// use img_to_video::convert::{convert_rgb_to_yuv420_filtered, ColorSpace, Downsampling, Matrix, Range};
+/// let color_space = ColorSpace { matrix: Matrix::Bt709, range: Range::Limited };
+/// let rgb = [255, 255, 255, 0, 0, 0, 255, 255, 255, 0, 0, 0];
+/// let yuv = convert_rgb_to_yuv420_filtered(2, 2, &rgb, 3, Downsampling::Box, color_space);
+/// assert_eq!(yuv, vec![235, 16, 235, 16, 128, 128]);
+/// ```
+pub fn convert_rgb_to_yuv420_filtered(
+    width: u32,
+    height: u32,
+    rgb: &[u8],
+    bytes_per_pixel: usize,
+    filter: Downsampling,
+    color_space: ColorSpace,
+) -> Vec<u8> {
+    let pixels = match bytes_per_pixel {
+        3 => Pixels::Rgb8(rgb),
+        4 => Pixels::Rgba8(rgb),
+        _ => panic!("unsupported number of bytes per pixel: {}", bytes_per_pixel),
+    };
+    convert_to_yuv420(width, height, pixels, filter, color_space)
+}
+
+/// Convert pixels in any [Pixels] layout to YUV420 in `color_space`, downsampling
+/// the chroma with `filter`.
+///
+/// The chroma of each pixel is computed at full resolution, then filtered and
+/// rounded once, so a flat area keeps the chroma of its pixels.
+/// 8 bits RGB, RGBA, BGR and BGRA pixels are read in place, the other layouts
+/// are converted one row at a time.
+///
+/// # Arguments
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `pixels` - The pixels of the image.
+/// * `filter` - The chroma downsampling filter.
+/// * `color_space` - The matrix and range of the YUV values.
+///
+/// # Returns
+/// A YUV420 buffer array, of [yuv420_size] bytes.
+///
+/// # Example
+/// ```
+/// use img_to_video::convert::{convert_to_yuv420, ColorSpace, Downsampling, Pixels};
+/// let luma = [255, 0, 255, 0];
+/// let yuv = convert_to_yuv420(2, 2, Pixels::Luma8(&luma), Downsampling::Box, ColorSpace::default());
+/// assert_eq!(yuv, vec![255, 0, 255, 0, 128, 128]);
+/// ```
+pub fn convert_to_yuv420(
+    width: u32,
+    height: u32,
+    pixels: Pixels,
+    filter: Downsampling,
+    color_space: ColorSpace,
+) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let frame_size = width * height;
+    let chroma_width = width.div_ceil(2);
+    let chroma_height = height.div_ceil(2);
+    let mut yuv: Vec<u8> = vec![0; yuv420_size(width as u32, height as u32)];
+    let (luma, chroma) = yuv.split_at_mut(frame_size);
+    let (u_plane, v_plane) = chroma.split_at_mut(chroma_width * chroma_height);
+
+    let taps = filter.taps();
+    let weight: i32 = taps.iter().map(|(_, w)| w).sum::<i32>().pow(2);
+    assert!(
+        pixels.len() >= frame_size * pixels.channels(),
+        "{} components for a {}x{} image",
+        pixels.len(),
+        width,
+        height
+    );
+    let mut rows = ChromaRows::new(pixels, width, color_space.coefficients(), taps);
+    let (mut u_sum, mut v_sum) = (vec![0; chroma_width], vec![0; chroma_width]);
+    for cy in 0..chroma_height {
+        u_sum.fill(0);
+        v_sum.fill(0);
+        for &(dy, wy) in taps {
+            let row = (2 * cy).saturating_add_signed(dy).min(height - 1);
+            let (u_row, v_row) = rows.get(row, luma);
+            for (sum, value) in u_sum.iter_mut().zip(u_row) {
+                *sum += wy * value;
+            }
+            for (sum, value) in v_sum.iter_mut().zip(v_row) {
+                *sum += wy * value;
+            }
+        }
+        let chroma_row = cy * chroma_width..(cy + 1) * chroma_width;
+        for (out, &sum) in u_plane[chroma_row.clone()].iter_mut().zip(&u_sum) {
+            *out = scale_chroma(sum, weight);
+        }
+        for (out, &sum) in v_plane[chroma_row].iter_mut().zip(&v_sum) {
+            *out = scale_chroma(sum, weight);
+        }
+    }
+    yuv
+}
+
+/// Convert pixels in any [Pixels] layout to YUV422 in `color_space`, downsampling
+/// the chroma of each row with `filter`.
+///
+/// # Arguments
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `pixels` - The pixels of the image.
+/// * `filter` - The chroma downsampling filter, applied horizontally.
+/// * `color_space` - The matrix and range of the YUV values.
+///
+/// # Returns
+/// A YUV422 buffer array, of [PixelFormat::frame_size] bytes.
+///
+/// # Example
+/// ```
+/// use img_to_video::convert::{convert_to_yuv422, ColorSpace, Downsampling, Pixels};
+/// let rgb = [255, 0, 0, 255, 0, 0, 0, 0, 255, 0, 0, 255];
+/// let yuv = convert_to_yuv422(2, 2, Pixels::Rgb8(&rgb), Downsampling::Box, ColorSpace::default());
+/// assert_eq!(yuv, vec![77, 77, 29, 29, 85, 255, 255, 107]);
+/// ```
+pub fn convert_to_yuv422(
+    width: u32,
+    height: u32,
+    pixels: Pixels,
+    filter: Downsampling,
+    color_space: ColorSpace,
+) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let frame_size = width * height;
+    let chroma_width = width.div_ceil(2);
+    let mut yuv: Vec<u8> = vec![0; PixelFormat::I422.frame_size(width as u32, height as u32)];
+    let (luma, chroma) = yuv.split_at_mut(frame_size);
+    let (u_plane, v_plane) = chroma.split_at_mut(chroma_width * height);
+
+    let taps = filter.taps();
+    let weight: i32 = taps.iter().map(|(_, w)| w).sum();
+    assert!(
+        pixels.len() >= frame_size * pixels.channels(),
+        "{} components for a {}x{} image",
+        pixels.len(),
+        width,
+        height
+    );
+    let mut rows = ChromaRows::new(pixels, width, color_space.coefficients(), taps);
+    for row in 0..height {
+        let (u_row, v_row) = rows.get(row, luma);
+        let chroma_row = row * chroma_width..(row + 1) * chroma_width;
+        for (out, &sum) in u_plane[chroma_row.clone()].iter_mut().zip(u_row) {
+            *out = scale_chroma(sum, weight);
+        }
+        for (out, &sum) in v_plane[chroma_row].iter_mut().zip(v_row) {
+            *out = scale_chroma(sum, weight);
+        }
+    }
+    yuv
+}
+
+/// Convert the alpha of pixels in any [Pixels] layout to the YUV420 frame of
+/// a WebM alpha stream: the alpha is the luma, and the chroma is neutral.
+///
+/// # Arguments
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `pixels` - The pixels of the image.
+///
+/// # Returns
+/// A YUV420 buffer array, of [PixelFormat::frame_size] bytes.
+///
+/// # Example
+/// ```
+/// use img_to_video::convert::{convert_alpha_to_yuv420, Pixels};
+/// let rgba = [255, 0, 0, 0, 255, 0, 0, 64, 0, 0, 255, 128, 0, 0, 0, 255];
+/// let yuv = convert_alpha_to_yuv420(2, 2, Pixels::Rgba8(&rgba));
+/// assert_eq!(yuv, vec![0, 64, 128, 255, 128, 128]);
+/// ```
+pub fn convert_alpha_to_yuv420(width: u32, height: u32, pixels: Pixels) -> Vec<u8> {
+    let frame_size = (width * height) as usize;
+    assert!(
+        pixels.len() >= frame_size * pixels.channels(),
+        "{} components for a {}x{} image",
+        pixels.len(),
+        width,
+        height
+    );
+    let mut yuv = pixels.alpha(frame_size);
+    yuv.resize(PixelFormat::I420.frame_size(width, height), 128);
+    yuv
+}
+
+/// Convert pixels in any [Pixels] layout to YUV444 in `color_space`, keeping
+/// the chroma of every pixel.
+///
+/// # Arguments
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `pixels` - The pixels of the image.
+/// * `color_space` - The matrix and range of the YUV values.
+///
+/// # Returns
+/// A YUV444 buffer array, of 3 bytes per pixel.
+///
+/// # Example
+/// ```
+/// use img_to_video::convert::{convert_to_yuv444, ColorSpace, Pixels};
+/// let rgb = [255, 0, 0, 0, 0, 255];
+/// let yuv = convert_to_yuv444(2, 1, Pixels::Rgb8(&rgb), ColorSpace::default());
+/// assert_eq!(yuv, vec![77, 29, 85, 255, 255, 107]);
+/// ```
+pub fn convert_to_yuv444(
+    width: u32,
+    height: u32,
+    pixels: Pixels,
+    color_space: ColorSpace,
+) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let frame_size = width * height;
+    let mut yuv: Vec<u8> = vec![0; 3 * frame_size];
+    let (luma, chroma) = yuv.split_at_mut(frame_size);
+    let (u_plane, v_plane) = chroma.split_at_mut(frame_size);
+
+    assert!(
+        pixels.len() >= frame_size * pixels.channels(),
+        "{} components for a {}x{} image",
+        pixels.len(),
+        width,
+        height
+    );
+    let coefficients = color_space.coefficients();
+    let (mut u_row, mut v_row) = (vec![0; width], vec![0; width]);
+    let mut rgb_row = Vec::with_capacity(width * 3);
+    for row in 0..height {
+        let rgb = pixels.row(row, width, &mut rgb_row);
+        let coefficients = match rgb.bgr {
+            true => coefficients.bgr(),
+            false => coefficients,
+        };
+        let plane_row = row * width..(row + 1) * width;
+        simd::convert_pixels(
+            rgb.pixels,
+            rgb.bytes_per_pixel,
+            &coefficients,
+            &mut luma[plane_row.clone()],
+            &mut u_row,
+            &mut v_row,
+        );
+        for (out, &sum) in u_plane[plane_row.clone()].iter_mut().zip(&u_row) {
+            *out = scale_chroma(sum, 1);
+        }
+        for (out, &sum) in v_plane[plane_row].iter_mut().zip(&v_row) {
+            *out = scale_chroma(sum, 1);
+        }
+    }
+    yuv
+}
+
+/// Convert pixels in any [Pixels] layout to YUV in `format` and `color_space`.
+/// The chroma is downsampled with `filter` for the formats which need it.
+///
+/// # Returns
+/// A buffer array of [PixelFormat::frame_size] bytes.
+pub fn convert_to_yuv(
+    width: u32,
+    height: u32,
+    pixels: Pixels,
+    format: PixelFormat,
+    filter: Downsampling,
+    color_space: ColorSpace,
+) -> Vec<u8> {
+    match format {
+        PixelFormat::I420 => convert_to_yuv420(width, height, pixels, filter, color_space),
+        PixelFormat::I422 => convert_to_yuv422(width, height, pixels, filter, color_space),
+        PixelFormat::I444 => convert_to_yuv444(width, height, pixels, color_space),
+    }
+}
+
+/// Horizontally filtered chroma of the last rows of an image.
+///
+/// The chroma of a row is filtered when the row is first needed, which also
+/// computes its luma. Rows are kept by their index modulo 4, which is enough
+/// for the 4 rows of the widest filter.
+struct ChromaRows<'a> {
+    pixels: Pixels<'a>,
+    width: usize,
+    coefficients: Coefficients,
+    taps: &'static [(isize, i32)],
+    indices: [Option<usize>; 4],
+    u_rows: [Vec<i32>; 4],
+    v_rows: [Vec<i32>; 4],
+    // Chroma of each pixel of a row, with the border pixels repeated
+    // CHROMA_PADDING times on the left and twice that on the right.
+    u_pixels: Vec<i32>,
+    v_pixels: Vec<i32>,
+    // Row converted to 8 bits RGB, for the layouts which are not read in place.
+    rgb_row: Vec<u8>,
+}
+
+/// Number of pixels past the left border of a row read by the filters.
+const CHROMA_PADDING: usize = 1;
+
+impl<'a> ChromaRows<'a> {
+    fn new(
+        pixels: Pixels<'a>,
+        width: usize,
+        coefficients: Coefficients,
+        taps: &'static [(isize, i32)],
+    ) -> Self {
+        let chroma_width = width.div_ceil(2);
+        let padded_width = width + 3 * CHROMA_PADDING;
+        ChromaRows {
+            pixels,
+            width,
+            coefficients,
+            taps,
+            indices: [None; 4],
+            u_rows: std::array::from_fn(|_| vec![0; chroma_width]),
+            v_rows: std::array::from_fn(|_| vec![0; chroma_width]),
+            u_pixels: vec![0; padded_width],
+            v_pixels: vec![0; padded_width],
+            rgb_row: Vec::with_capacity(width * 3),
+        }
+    }
+
+    /// Returns the filtered chroma of a row, writing its luma into `luma` if it
+    /// was not computed yet.
+    fn get(&mut self, row: usize, luma: &mut [u8]) -> (&[i32], &[i32]) {
+        let slot = row % 4;
+        if self.indices[slot] != Some(row) {
+            self.convert_row(row, luma);
+            filter_row(&self.u_pixels, self.taps, &mut self.u_rows[slot]);
+            filter_row(&self.v_pixels, self.taps, &mut self.v_rows[slot]);
+            self.indices[slot] = Some(row);
+        }
+        (&self.u_rows[slot], &self.v_rows[slot])
+    }
+
+    fn convert_row(&mut self, row: usize, luma: &mut [u8]) {
+        let width = self.width;
+        let pixels = CHROMA_PADDING..CHROMA_PADDING + width;
+        let rgb = self.pixels.row(row, width, &mut self.rgb_row);
+        let coefficients = match rgb.bgr {
+            true => self.coefficients.bgr(),
+            false => self.coefficients,
+        };
+        simd::convert_pixels(
+            rgb.pixels,
+            rgb.bytes_per_pixel,
+            &coefficients,
+            &mut luma[row * width..(row + 1) * width],
+            &mut self.u_pixels[pixels.clone()],
+            &mut self.v_pixels[pixels.clone()],
+        );
+        for chroma in [&mut self.u_pixels, &mut self.v_pixels] {
+            let (first, last) = (chroma[pixels.start], chroma[pixels.end - 1]);
+            chroma[..pixels.start].fill(first);
+            chroma[pixels.end..].fill(last);
+        }
+    }
+}
+
+/// Filter the padded chroma of a row at every other pixel.
+fn filter_row(pixels: &[i32], taps: &[(isize, i32)], filtered: &mut [i32]) {
+    match taps.len() {
+        2 => filter_row_with::<2>(pixels, taps, filtered),
+        4 => filter_row_with::<4>(pixels, taps, filtered),
+        _ => unreachable!("unsupported filter"),
+    }
+}
+
+/// Filter a row with `N` consecutive taps, known at compile time so the sums are unrolled.
+fn filter_row_with<const N: usize>(pixels: &[i32], taps: &[(isize, i32)], filtered: &mut [i32]) {
+    let weights: [i32; N] = std::array::from_fn(|index| taps[index].1);
+    let first = CHROMA_PADDING.wrapping_add_signed(taps[0].0);
+    let windows = pixels[first..].windows(N).step_by(2);
+    for (out, window) in filtered.iter_mut().zip(windows) {
+        *out = window
+            .iter()
+            .zip(weights)
+            .map(|(pixel, weight)| weight * pixel)
+            .sum();
+    }
+}
+
+/// Scale a weighted sum of chroma to 8 bits, rounding like [convert_rgb_to_yuv420_pixel].
+/// The weight is a power of two, so the division is a shift.
+fn scale_chroma(sum: i32, weight: i32) -> u8 {
+    (((sum + 128 * weight) >> (8 + weight.trailing_zeros())) + 128).clamp(0, 255) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn yuv_pixel_1() {
+        let yuv_pixel = convert_rgb_to_yuv420_pixel(255.0, 0.0, 0.0);
+        assert_eq!(yuv_pixel.y, 77);
+        assert_eq!(yuv_pixel.u, 85);
+        assert_eq!(yuv_pixel.v, 255);
+    }
+
+    #[test]
+    fn yuv_pixel_2() {
+        let yuv_pixel = convert_rgb_to_yuv420_pixel(0.0, 255.0, 0.0);
+        assert_eq!(yuv_pixel.y, 149);
+        assert_eq!(yuv_pixel.u, 44);
+        assert_eq!(yuv_pixel.v, 22);
+    }
+
+    #[test]
+    fn yuv_pixel_3() {
+        let yuv_pixel = convert_rgb_to_yuv420_pixel(0.0, 0.0, 255.0);
+        assert_eq!(yuv_pixel.y, 29);
+        assert_eq!(yuv_pixel.u, 255);
+        assert_eq!(yuv_pixel.v, 107);
+    }
+
+    #[test]
+    fn yuv_pixel_4() {
+        let yuv_pixel = convert_rgb_to_yuv420_pixel(255.0, 255.0, 255.0);
+        assert_eq!(yuv_pixel.y, 255);
+        assert_eq!(yuv_pixel.u, 128);
+        assert_eq!(yuv_pixel.v, 128);
+    }
+
+    #[test]
+    fn rgb_to_yuv_1() {
+        let rgb = vec![255, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let yuv = convert_rgb_to_yuv420(2, 2, &rgb, 3);
+        assert_eq!(yuv, vec![255, 0, 0, 0, 128, 128]);
+    }
+
+    #[test]
+    fn rgb_to_yuv_2() {
+        let rgb = vec![255, 255, 255, 255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let yuv = convert_rgb_to_yuv420(2, 2, &rgb, 3);
+        assert_eq!(yuv, vec![255, 77, 149, 29, 128, 128]);
+    }
+
+    #[test]
+    fn rgb_to_yuv_3() {
+        let rgb = vec![255, 0, 0, 255, 0, 0, 255, 0, 0, 255, 0, 0];
+        let yuv = convert_rgb_to_yuv420(2, 2, &rgb, 3);
+        assert_eq!(yuv, vec![77, 77, 77, 77, 85, 255]);
+    }
+
+    #[test]
+    fn rgb_to_yuv_4() {
+        let rgb = vec![0, 255, 0, 255, 0, 0, 255, 0, 0, 0, 255, 0];
+        let yuv = convert_rgb_to_yuv420(2, 2, &rgb, 3);
+        assert_eq!(yuv, vec![149, 77, 77, 149, 65, 138]);
+    }
+
+    #[test]
+    fn rgb_to_yuv_5() {
+        let rgb = vec![0, 0, 255, 255, 255, 255, 255, 255, 255, 0, 0, 255];
+        let yuv = convert_rgb_to_yuv420(2, 2, &rgb, 3);
+        assert_eq!(yuv, vec![29, 255, 255, 29, 191, 118]);
+    }
+
+    #[test]
+    fn rgb_to_yuv_6() {
+        let rgb = vec![255, 255, 255, 255, 255, 255, 0, 0, 0, 255, 0, 0];
+        let yuv = convert_rgb_to_yuv420(2, 2, &rgb, 3);
+        assert_eq!(yuv, vec![255, 255, 0, 77, 117, 160]);
+    }
+
+    #[test]
+    fn rgb_to_yuv_7() {
+        let rgb = vec![42, 42, 42, 42, 42, 0, 42, 42, 0, 42, 42, 42];
+        let yuv = convert_rgb_to_yuv420(2, 2, &rgb, 3);
+        assert_eq!(yuv, vec![42, 37, 37, 42, 118, 130]);
+    }
+
+    #[test]
+    fn rgb_to_yuv_8() {
+        let rgb = vec![42, 42, 42, 0, 42, 0, 0, 42, 0, 0, 42, 0];
+        let yuv = convert_rgb_to_yuv420(2, 2, &rgb, 3);
+        assert_eq!(yuv, vec![42, 25, 25, 25, 118, 115]);
+    }
+
+    #[test]
+    fn rgb_to_yuv_4_bytes() {
+        let rgb = vec![42, 42, 42, 0, 0, 42, 0, 0, 0, 42, 0, 0, 0, 42, 0, 0];
+        let yuv = convert_rgb_to_yuv420(2, 2, &rgb, 4);
+        assert_eq!(yuv, vec![42, 25, 25, 25, 118, 115]);
+    }
+
+    #[test]
+    fn rgb_to_yuv_odd_width() {
+        // Red, green, blue on the first row, white on the second.
+        let rgb = vec![
+            255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
+        ];
+        let yuv = convert_rgb_to_yuv420(3, 2, &rgb, 3);
+        assert_eq!(yuv, vec![77, 149, 29, 255, 255, 255, 96, 191, 133, 118]);
+    }
+
+    #[test]
+    fn rgb_to_yuv_odd_height() {
+        let rgb = vec![
+            255, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 0, 0, 0, 0, 255,
+        ];
+        let yuv = convert_rgb_to_yuv420(2, 3, &rgb, 3);
+        assert_eq!(yuv, vec![255, 0, 0, 0, 77, 29, 128, 170, 128, 181]);
+    }
+
+    #[test]
+    fn rgb_to_yuv_single_pixel() {
+        let yuv = convert_rgb_to_yuv420(1, 1, &[0, 0, 255], 3);
+        assert_eq!(yuv, vec![29, 255, 107]);
+    }
+
+    #[test]
+    fn rgb_to_yuv_1919x1079() {
+        let (width, height) = (1919, 1079);
+        let mut rgb = vec![0; (width * height * 3) as usize];
+        // Blue last column, red last row.
+        for j in 0..height {
+            let index = ((j * width + width - 1) * 3) as usize;
+            rgb[index + 2] = 255;
+        }
+        for i in 0..width - 1 {
+            let index = (((height - 1) * width + i) * 3) as usize;
+            rgb[index] = 255;
+        }
+        let yuv = convert_rgb_to_yuv420(width, height, &rgb, 3);
+        assert_eq!(yuv.len(), yuv420_size(width, height));
+        assert_eq!(yuv.len(), 1919 * 1079 + 2 * 960 * 540);
+
+        let frame_size = (width * height) as usize;
+        let (chroma_width, chroma_height) = (960, 540);
+        let chroma_size = chroma_width * chroma_height;
+        let u = |x: usize, y: usize| yuv[frame_size + y * chroma_width + x];
+        let v = |x: usize, y: usize| yuv[frame_size + chroma_size + y * chroma_width + x];
+        assert_eq!((u(0, 0), v(0, 0)), (128, 128));
+        assert_eq!((u(959, 0), v(959, 0)), (255, 107));
+        assert_eq!((u(959, 539), v(959, 539)), (255, 107));
+        assert_eq!((u(0, 539), v(0, 539)), (85, 255));
+        assert_eq!((u(958, 539), v(958, 539)), (85, 255));
+    }
+
+    /// Straightforward conversion, filtering the chroma of the pixels in floating point.
+    fn reference_yuv420(width: usize, height: usize, rgb: &[u8], filter: Downsampling) -> Vec<u8> {
+        let pixel = |x: isize, y: isize| {
+            let x = x.clamp(0, width as isize - 1) as usize;
+            let y = y.clamp(0, height as isize - 1) as usize;
+            let index = (y * width + x) * 3;
+            let (r, g, b) = (
+                rgb[index] as f64,
+                rgb[index + 1] as f64,
+                rgb[index + 2] as f64,
+            );
+            (
+                -43.0 * r - 84.0 * g + 127.0 * b,
+                127.0 * r - 106.0 * g - 21.0 * b,
+            )
+        };
+        let weights: &[(isize, f64)] = match filter {
+            Downsampling::Box => &[(0, 0.5), (1, 0.5)],
+            Downsampling::Triangle => &[(-1, 0.125), (0, 0.375), (1, 0.375), (2, 0.125)],
+        };
+        let scale =
+            |value: f64| (((value + 128.0) / 256.0).floor() + 128.0).clamp(0.0, 255.0) as u8;
+
+        let mut y_plane = Vec::new();
+        for index in 0..width * height {
+            let [r, g, b] = [rgb[index * 3], rgb[index * 3 + 1], rgb[index * 3 + 2]];
+            y_plane.push(convert_rgb_to_yuv420_pixel(r as f32, g as f32, b as f32).y);
+        }
+        let (mut u_plane, mut v_plane) = (Vec::new(), Vec::new());
+        for cy in 0..height.div_ceil(2) as isize {
+            for cx in 0..width.div_ceil(2) as isize {
+                let (mut u, mut v) = (0.0, 0.0);
+                for &(dy, wy) in weights {
+                    for &(dx, wx) in weights {
+                        let (pu, pv) = pixel(2 * cx + dx, 2 * cy + dy);
+                        u += wx * wy * pu;
+                        v += wx * wy * pv;
+                    }
+                }
+                u_plane.push(scale(u));
+                v_plane.push(scale(v));
+            }
+        }
+        [y_plane, u_plane, v_plane].concat()
+    }
+
+    /// Returns a deterministic pseudo-random RGB image.
+    fn noise(width: usize, height: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..width * height * 3)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_reference() {
+        for filter in [Downsampling::Box, Downsampling::Triangle] {
+            for (width, height) in [(2, 2), (3, 3), (8, 6), (17, 5), (1, 7), (33, 32)] {
+                let rgb = noise(width, height, (width * height) as u32);
+                assert_eq!(
+                    convert_rgb_to_yuv420_filtered(
+                        width as u32,
+                        height as u32,
+                        &rgb,
+                        3,
+                        filter,
+                        ColorSpace::default()
+                    ),
+                    reference_yuv420(width, height, &rgb, filter),
+                    "{:?} {}x{}",
+                    filter,
+                    width,
+                    height
+                );
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn random_images_match_reference(
+            width in 1usize..48,
+            height in 1usize..12,
+            seed in any::<u32>(),
+            triangle in any::<bool>(),
+        ) {
+            let filter = match triangle {
+                true => Downsampling::Triangle,
+                false => Downsampling::Box,
+            };
+            let rgb = noise(width, height, seed);
+            // The same pixels with an alpha channel.
+            let rgba: Vec<u8> = rgb.chunks(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect();
+            let expected = reference_yuv420(width, height, &rgb, filter);
+            for (pixels, bytes_per_pixel) in [(&rgb, 3), (&rgba, 4)] {
+                let yuv = convert_rgb_to_yuv420_filtered(
+                    width as u32,
+                    height as u32,
+                    pixels,
+                    bytes_per_pixel,
+                    filter,
+                    ColorSpace::default(),
+                );
+                prop_assert_eq!(&yuv, &expected);
+            }
+        }
+    }
+
+    #[test]
+    fn flat_chroma_is_kept() {
+        let rgb: Vec<u8> = [200, 30, 90].repeat(6 * 6);
+        let expected = convert_rgb_to_yuv420_pixel(200.0, 30.0, 90.0);
+        for filter in [Downsampling::Box, Downsampling::Triangle] {
+            let yuv = convert_rgb_to_yuv420_filtered(6, 6, &rgb, 3, filter, ColorSpace::default());
+            assert!(yuv[36..45].iter().all(|&u| u == expected.u));
+            assert!(yuv[45..].iter().all(|&v| v == expected.v));
+        }
+    }
+
+    #[test]
+    fn yuv444_matches_pixel_conversion() {
+        let (width, height) = (13, 5);
+        let rgb = noise(width, height, 7);
+        let yuv = convert_to_yuv444(
+            width as u32,
+            height as u32,
+            Pixels::Rgb8(&rgb),
+            ColorSpace::default(),
+        );
+        let size = width * height;
+        for (index, pixel) in rgb.chunks(3).enumerate() {
+            let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(f32::from);
+            let expected = convert_rgb_to_yuv420_pixel(r, g, b);
+            let actual = [yuv[index], yuv[size + index], yuv[2 * size + index]];
+            assert_eq!(
+                actual,
+                [expected.y, expected.u, expected.v],
+                "pixel {}",
+                index
+            );
+        }
+        let yuv420 = convert_rgb_to_yuv420(width as u32, height as u32, &rgb, 3);
+        assert_eq!(yuv[..size], yuv420[..size]);
+    }
+
+    #[test]
+    fn color_spaces() {
+        // White, black, red and a gray, one per 2x2 block.
+        let rgb = [[255, 255, 255], [0, 0, 0], [255, 0, 0], [128, 128, 128]].map(|p| p.repeat(4));
+        let expected = [
+            (
+                Matrix::Bt601,
+                Range::Full,
+                [255, 0, 77, 128],
+                [128, 128, 85, 128],
+                [128, 128, 255, 128],
+            ),
+            (
+                Matrix::Bt601,
+                Range::Limited,
+                [235, 16, 82, 126],
+                [128, 128, 90, 128],
+                [128, 128, 240, 128],
+            ),
+            (
+                Matrix::Bt709,
+                Range::Full,
+                [255, 0, 54, 128],
+                [128, 128, 99, 128],
+                [128, 128, 255, 128],
+            ),
+            (
+                Matrix::Bt709,
+                Range::Limited,
+                [235, 16, 63, 126],
+                [128, 128, 102, 128],
+                [128, 128, 240, 128],
+            ),
+            (
+                Matrix::Bt2020,
+                Range::Full,
+                [255, 0, 67, 128],
+                [128, 128, 93, 128],
+                [128, 128, 255, 128],
+            ),
+            (
+                Matrix::Bt2020,
+                Range::Limited,
+                [235, 16, 74, 126],
+                [128, 128, 97, 128],
+                [128, 128, 240, 128],
+            ),
+        ];
+        for (matrix, range, y, u, v) in expected {
+            let color_space = ColorSpace { matrix, range };
+            for (index, rgb) in rgb.iter().enumerate() {
+                let yuv =
+                    convert_rgb_to_yuv420_filtered(2, 2, rgb, 3, Downsampling::Box, color_space);
+                assert_eq!(
+                    yuv,
+                    vec![y[index], y[index], y[index], y[index], u[index], v[index]],
+                    "{:?} {:?} {:?}",
+                    matrix,
+                    range,
+                    &rgb[..3]
+                );
+            }
+        }
+    }
+}
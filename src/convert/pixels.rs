@@ -0,0 +1,348 @@
+//! Pixel layouts accepted by the converter.
+
+use image::DynamicImage;
+
+/// Pixels of an image, row after row, in one of the supported layouts.
+///
+/// 16 bits and floating point components are scaled to 8 bits, or to the bit
+/// depth of the video. Floating point components are not tone mapped: they are
+/// clipped to `0.0..=1.0`, so the highlights of HDR images, like EXR or Radiance
+/// HDR frames with values above 1.0, are flat white unless they are tone mapped
+/// beforehand. Alpha is ignored.
+#[derive(Debug, Clone, Copy)]
+pub enum Pixels<'a> {
+    Luma8(&'a [u8]),
+    LumaA8(&'a [u8]),
+    Rgb8(&'a [u8]),
+    Rgba8(&'a [u8]),
+    Bgr8(&'a [u8]),
+    Bgra8(&'a [u8]),
+    Luma16(&'a [u16]),
+    LumaA16(&'a [u16]),
+    Rgb16(&'a [u16]),
+    Rgba16(&'a [u16]),
+    Rgb32F(&'a [f32]),
+    Rgba32F(&'a [f32]),
+}
+
+/// A row of 8 bits RGB or BGR pixels, as read by the conversion kernels.
+pub(super) struct Row<'b> {
+    pub(super) pixels: &'b [u8],
+    pub(super) bytes_per_pixel: usize,
+    /// The blue component comes first.
+    pub(super) bgr: bool,
+}
+
+impl<'a> Pixels<'a> {
+    /// Returns the pixels of an image of the image crate, without copying them.
+    /// Returns `None` for layouts added to the image crate which are not supported yet.
+    pub fn from_image(image: &'a DynamicImage) -> Option<Self> {
+        Some(match image {
+            DynamicImage::ImageLuma8(image) => Pixels::Luma8(image.as_raw()),
+            DynamicImage::ImageLumaA8(image) => Pixels::LumaA8(image.as_raw()),
+            DynamicImage::ImageRgb8(image) => Pixels::Rgb8(image.as_raw()),
+            DynamicImage::ImageRgba8(image) => Pixels::Rgba8(image.as_raw()),
+            DynamicImage::ImageLuma16(image) => Pixels::Luma16(image.as_raw()),
+            DynamicImage::ImageLumaA16(image) => Pixels::LumaA16(image.as_raw()),
+            DynamicImage::ImageRgb16(image) => Pixels::Rgb16(image.as_raw()),
+            DynamicImage::ImageRgba16(image) => Pixels::Rgba16(image.as_raw()),
+            DynamicImage::ImageRgb32F(image) => Pixels::Rgb32F(image.as_raw()),
+            DynamicImage::ImageRgba32F(image) => Pixels::Rgba32F(image.as_raw()),
+            _ => return None,
+        })
+    }
+
+    /// Returns the number of components of each pixel.
+    pub fn channels(&self) -> usize {
+        match self {
+            Pixels::Luma8(_) | Pixels::Luma16(_) => 1,
+            Pixels::LumaA8(_) | Pixels::LumaA16(_) => 2,
+            Pixels::Rgb8(_) | Pixels::Bgr8(_) | Pixels::Rgb16(_) | Pixels::Rgb32F(_) => 3,
+            Pixels::Rgba8(_) | Pixels::Bgra8(_) | Pixels::Rgba16(_) | Pixels::Rgba32F(_) => 4,
+        }
+    }
+
+    /// Returns the number of components of the buffer.
+    pub fn len(&self) -> usize {
+        match self {
+            Pixels::Luma8(data)
+            | Pixels::LumaA8(data)
+            | Pixels::Rgb8(data)
+            | Pixels::Rgba8(data)
+            | Pixels::Bgr8(data)
+            | Pixels::Bgra8(data) => data.len(),
+            Pixels::Luma16(data)
+            | Pixels::LumaA16(data)
+            | Pixels::Rgb16(data)
+            | Pixels::Rgba16(data) => data.len(),
+            Pixels::Rgb32F(data) | Pixels::Rgba32F(data) => data.len(),
+        }
+    }
+
+    /// Returns true if the buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the 8 bits alpha of the first `count` pixels, fully opaque for the
+    /// layouts without alpha.
+    pub fn alpha(&self, count: usize) -> Vec<u8> {
+        let channels = self.channels();
+        let last = |pixel: usize| (pixel + 1) * channels - 1;
+        match *self {
+            Pixels::LumaA8(data) | Pixels::Rgba8(data) | Pixels::Bgra8(data) => {
+                (0..count).map(|pixel| data[last(pixel)]).collect()
+            }
+            Pixels::LumaA16(data) | Pixels::Rgba16(data) => (0..count)
+                .map(|pixel| scale_u16(data[last(pixel)]))
+                .collect(),
+            Pixels::Rgba32F(data) => (0..count)
+                .map(|pixel| scale_f32(data[last(pixel)]))
+                .collect(),
+            Pixels::Luma8(_)
+            | Pixels::Rgb8(_)
+            | Pixels::Bgr8(_)
+            | Pixels::Luma16(_)
+            | Pixels::Rgb16(_)
+            | Pixels::Rgb32F(_) => vec![u8::MAX; count],
+        }
+    }
+
+    /// Returns the pixels of a row as 8 bits RGB or BGR, converting them into
+    /// `scratch` if they are in another layout.
+    pub(super) fn row<'b>(&'b self, row: usize, width: usize, scratch: &'b mut Vec<u8>) -> Row<'b> {
+        let channels = self.channels();
+        let samples = row * width * channels..(row + 1) * width * channels;
+        let direct = |pixels: &'b [u8], bgr| Row {
+            pixels: &pixels[samples.clone()],
+            bytes_per_pixel: channels,
+            bgr,
+        };
+        match *self {
+            Pixels::Rgb8(data) | Pixels::Rgba8(data) => return direct(data, false),
+            Pixels::Bgr8(data) | Pixels::Bgra8(data) => return direct(data, true),
+            Pixels::Luma8(data) | Pixels::LumaA8(data) => {
+                expand(&data[samples], channels, |value| value, scratch)
+            }
+            Pixels::Luma16(data)
+            | Pixels::LumaA16(data)
+            | Pixels::Rgb16(data)
+            | Pixels::Rgba16(data) => expand(&data[samples], channels, scale_u16, scratch),
+            Pixels::Rgb32F(data) | Pixels::Rgba32F(data) => {
+                expand(&data[samples], channels, scale_f32, scratch)
+            }
+        }
+        Row {
+            pixels: scratch,
+            bytes_per_pixel: 3,
+            bgr: false,
+        }
+    }
+
+    /// Convert the pixels of a row to RGB components in `0.0..=1.0`, keeping the
+    /// precision of 16 bits and floating point layouts. Floating point components
+    /// outside of that range are clipped.
+    pub(super) fn row_f32(&self, row: usize, width: usize, rgb: &mut Vec<f32>) {
+        let channels = self.channels();
+        let samples = row * width * channels..(row + 1) * width * channels;
+        let unit_u8 = |value: u8| value as f32 / 255.0;
+        match *self {
+            Pixels::Luma8(data)
+            | Pixels::LumaA8(data)
+            | Pixels::Rgb8(data)
+            | Pixels::Rgba8(data) => expand(&data[samples], channels, unit_u8, rgb),
+            Pixels::Bgr8(data) | Pixels::Bgra8(data) => {
+                expand(&data[samples], channels, unit_u8, rgb);
+                for pixel in rgb.chunks_exact_mut(3) {
+                    pixel.swap(0, 2);
+                }
+            }
+            Pixels::Luma16(data)
+            | Pixels::LumaA16(data)
+            | Pixels::Rgb16(data)
+            | Pixels::Rgba16(data) => expand(
+                &data[samples],
+                channels,
+                |value| value as f32 / 65535.0,
+                rgb,
+            ),
+            Pixels::Rgb32F(data) | Pixels::Rgba32F(data) => {
+                expand(&data[samples], channels, |value| value.clamp(0.0, 1.0), rgb)
+            }
+        }
+    }
+}
+
+/// Convert pixels with 1 to 4 components to RGB, repeating the luma of gray pixels.
+fn expand<T: Copy, U: Copy>(
+    samples: &[T],
+    channels: usize,
+    scale: impl Fn(T) -> U,
+    rgb: &mut Vec<U>,
+) {
+    rgb.clear();
+    for pixel in samples.chunks_exact(channels) {
+        match channels {
+            1 | 2 => {
+                let luma = scale(pixel[0]);
+                rgb.extend([luma, luma, luma]);
+            }
+            _ => rgb.extend([scale(pixel[0]), scale(pixel[1]), scale(pixel[2])]),
+        }
+    }
+}
+
+/// Scale a 16 bits component to 8 bits, rounding to the nearest value.
+fn scale_u16(value: u16) -> u8 {
+    ((value as u32 * 255 + 32767) / 65535) as u8
+}
+
+/// Scale a floating point component in `0.0..=1.0` to 8 bits, rounding to the nearest value.
+/// Components outside of that range are clipped.
+fn scale_f32(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::{convert_to_yuv420, ColorSpace, Downsampling, Matrix, Range};
+
+    fn convert(width: u32, height: u32, pixels: Pixels) -> Vec<u8> {
+        let color_space = ColorSpace {
+            matrix: Matrix::Bt709,
+            range: Range::Limited,
+        };
+        convert_to_yuv420(width, height, pixels, Downsampling::Triangle, color_space)
+    }
+
+    #[test]
+    fn layouts_match_rgb8() {
+        let (width, height) = (7, 5);
+        let rgb: Vec<u8> = (0..width * height * 3)
+            .map(|i| (i * 37 % 256) as u8)
+            .collect();
+        let expected = convert(width, height, Pixels::Rgb8(&rgb));
+
+        let with_alpha = |values: &[u8]| -> Vec<u8> {
+            values
+                .chunks(3)
+                .flat_map(|p| [p[0], p[1], p[2], 7])
+                .collect()
+        };
+        let bgr: Vec<u8> = rgb.chunks(3).flat_map(|p| [p[2], p[1], p[0]]).collect();
+        let rgb16: Vec<u16> = rgb.iter().map(|&v| v as u16 * 257).collect();
+        let rgba16: Vec<u16> = with_alpha(&rgb).iter().map(|&v| v as u16 * 257).collect();
+        let rgb32f: Vec<f32> = rgb.iter().map(|&v| v as f32 / 255.0).collect();
+        let rgba32f: Vec<f32> = with_alpha(&rgb).iter().map(|&v| v as f32 / 255.0).collect();
+
+        let rgba = with_alpha(&rgb);
+        let bgra = with_alpha(&bgr);
+        for pixels in [
+            Pixels::Rgba8(&rgba),
+            Pixels::Bgr8(&bgr),
+            Pixels::Bgra8(&bgra),
+            Pixels::Rgb16(&rgb16),
+            Pixels::Rgba16(&rgba16),
+            Pixels::Rgb32F(&rgb32f),
+            Pixels::Rgba32F(&rgba32f),
+        ] {
+            assert_eq!(convert(width, height, pixels), expected, "{:?}", pixels);
+        }
+    }
+
+    #[test]
+    fn gray_layouts() {
+        let (width, height) = (3, 3);
+        let luma: Vec<u8> = (0..width * height).map(|i| (i * 29) as u8).collect();
+        let rgb: Vec<u8> = luma.iter().flat_map(|&v| [v, v, v]).collect();
+        let expected = convert(width, height, Pixels::Rgb8(&rgb));
+
+        let luma_alpha: Vec<u8> = luma.iter().flat_map(|&v| [v, 0]).collect();
+        let luma16: Vec<u16> = luma.iter().map(|&v| v as u16 * 257).collect();
+        let luma_alpha16: Vec<u16> = luma16.iter().flat_map(|&v| [v, 0]).collect();
+        for pixels in [
+            Pixels::Luma8(&luma),
+            Pixels::LumaA8(&luma_alpha),
+            Pixels::Luma16(&luma16),
+            Pixels::LumaA16(&luma_alpha16),
+        ] {
+            assert_eq!(convert(width, height, pixels), expected, "{:?}", pixels);
+        }
+        // Grays have a neutral chroma.
+        assert!(expected[9..].iter().all(|&c| c == 128));
+    }
+
+    #[test]
+    fn scaling() {
+        assert_eq!(scale_u16(0), 0);
+        assert_eq!(scale_u16(128), 0);
+        assert_eq!(scale_u16(129), 1);
+        assert_eq!(scale_u16(65535), 255);
+        assert_eq!(scale_f32(-0.5), 0);
+        assert_eq!(scale_f32(0.5), 128);
+        assert_eq!(scale_f32(4.0), 255);
+    }
+
+    #[test]
+    fn float_rows() {
+        let mut rgb = Vec::new();
+        Pixels::Bgra8(&[255, 0, 51, 0]).row_f32(0, 1, &mut rgb);
+        assert_eq!(rgb, vec![0.2, 0.0, 1.0]);
+        Pixels::LumaA16(&[0, 1, 65535, 1]).row_f32(0, 2, &mut rgb);
+        assert_eq!(rgb, vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0]);
+        Pixels::Rgb32F(&[-1.0, 0.25, 2.0]).row_f32(0, 1, &mut rgb);
+        assert_eq!(rgb, vec![0.0, 0.25, 1.0]);
+    }
+
+    #[test]
+    fn hdr_highlights_clip() {
+        use crate::convert::{convert_to_yuv_high, PixelFormat};
+
+        // White, and highlights 1.5 to 16 times brighter than white.
+        let (width, height) = (4, 2);
+        let white = vec![1.0; 4 * 2 * 3];
+        let hdr: Vec<f32> = (0..4 * 2 * 3).map(|i| 1.5 + i as f32 * 0.63).collect();
+        assert_eq!(
+            convert(width, height, Pixels::Rgb32F(&hdr)),
+            convert(width, height, Pixels::Rgb32F(&white))
+        );
+        let high = |pixels| {
+            convert_to_yuv_high(
+                width,
+                height,
+                pixels,
+                PixelFormat::I444,
+                10,
+                Downsampling::Box,
+                ColorSpace::default(),
+            )
+        };
+        let expected = high(Pixels::Rgb32F(&white));
+        assert_eq!(high(Pixels::Rgb32F(&hdr)), expected);
+        // Full range white is the largest 10 bits luma.
+        assert!(expected[..8].iter().all(|&y| y == 1023));
+    }
+
+    #[test]
+    fn alpha() {
+        assert_eq!(
+            Pixels::Bgra8(&[1, 2, 3, 4, 5, 6, 7, 8]).alpha(2),
+            vec![4, 8]
+        );
+        assert_eq!(Pixels::LumaA16(&[0, 65535, 0, 0]).alpha(2), vec![255, 0]);
+        assert_eq!(Pixels::Rgba32F(&[0.0, 0.0, 0.0, 0.5]).alpha(1), vec![128]);
+        assert_eq!(Pixels::Rgb8(&[0; 6]).alpha(2), vec![255, 255]);
+    }
+
+    #[test]
+    fn images_are_not_copied() {
+        let image = DynamicImage::new_rgba16(2, 2);
+        match Pixels::from_image(&image) {
+            Some(Pixels::Rgba16(data)) => {
+                assert_eq!(data.as_ptr(), image.as_rgba16().unwrap().as_ptr())
+            }
+            pixels => panic!("unexpected layout: {:?}", pixels),
+        }
+    }
+}
@@ -0,0 +1,409 @@
+//! Per-pixel conversion kernels.
+//!
+//! The luma and the full resolution chroma of the pixels are computed with the
+//! fixed point [Coefficients] of a colour space. Vectorised kernels are selected
+//! at runtime from the features of the CPU: AVX2 or SSE4.1 on x86_64 and NEON on
+//! aarch64. They only handle 3 and 4 bytes per pixel, and leave the last pixels,
+//! which do not fill a vector load, to the scalar kernel. All kernels give the
+//! same results.
+
+use super::Coefficients;
+
+/// Compute the luma and the chroma numerators of each pixel of `rgb`.
+///
+/// `y`, `u` and `v` must have one element per pixel of `rgb`.
+///
+/// # Panics
+/// If `u`, `v` or `rgb` are shorter than `y`, as the vectorised kernels only
+/// bound their loops by `y` and `rgb`.
+pub(super) fn convert_pixels(
+    rgb: &[u8],
+    bytes_per_pixel: usize,
+    coefficients: &Coefficients,
+    y: &mut [u8],
+    u: &mut [i32],
+    v: &mut [i32],
+) {
+    assert!(u.len() >= y.len() && v.len() >= y.len() && rgb.len() >= y.len() * bytes_per_pixel);
+    let done = convert_pixels_vectorised(rgb, bytes_per_pixel, coefficients, y, u, v);
+    convert_pixels_scalar(
+        &rgb[done * bytes_per_pixel..],
+        bytes_per_pixel,
+        coefficients,
+        &mut y[done..],
+        &mut u[done..],
+        &mut v[done..],
+    );
+}
+
+/// Run the best vectorised kernel available, returning the number of pixels converted.
+#[allow(unused_variables)]
+fn convert_pixels_vectorised(
+    rgb: &[u8],
+    bytes_per_pixel: usize,
+    coefficients: &Coefficients,
+    y: &mut [u8],
+    u: &mut [i32],
+    v: &mut [i32],
+) -> usize {
+    if !matches!(bytes_per_pixel, 3 | 4) {
+        return 0;
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: the CPU supports AVX2.
+            return unsafe {
+                x86::convert_pixels_avx2(rgb, bytes_per_pixel, coefficients, y, u, v)
+            };
+        }
+        if is_x86_feature_detected!("sse4.1") {
+            // SAFETY: the CPU supports SSE4.1.
+            return unsafe {
+                x86::convert_pixels_sse41(rgb, bytes_per_pixel, coefficients, y, u, v)
+            };
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            // SAFETY: the CPU supports NEON.
+            return unsafe {
+                aarch64::convert_pixels_neon(rgb, bytes_per_pixel, coefficients, y, u, v)
+            };
+        }
+    }
+    0
+}
+
+/// Scalar kernel, used for the pixels left by the vectorised kernels.
+pub(super) fn convert_pixels_scalar(
+    rgb: &[u8],
+    bytes_per_pixel: usize,
+    coefficients: &Coefficients,
+    y: &mut [u8],
+    u: &mut [i32],
+    v: &mut [i32],
+) {
+    let Coefficients {
+        y: cy,
+        u: cu,
+        v: cv,
+        y_offset,
+    } = *coefficients;
+    for (index, pixel) in rgb.chunks_exact(bytes_per_pixel).enumerate() {
+        let (r, g, b) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+        y[index] =
+            (((cy[0] * r + cy[1] * g + cy[2] * b + 128) >> 8) + y_offset).clamp(0, 255) as u8;
+        u[index] = cu[0] * r + cu[1] * g + cu[2] * b;
+        v[index] = cv[0] * r + cv[1] * g + cv[2] * b;
+    }
+}
+
+/// Returns the byte shuffles moving the red, green and blue components of four
+/// pixels into the low byte of four 32 bits lanes, and zeroing the other bytes.
+#[cfg(target_arch = "x86_64")]
+fn channel_shuffles(bytes_per_pixel: usize) -> [[i8; 16]; 3] {
+    let mut shuffles = [[-1; 16]; 3];
+    for (channel, shuffle) in shuffles.iter_mut().enumerate() {
+        for lane in 0..4 {
+            shuffle[lane * 4] = (lane * bytes_per_pixel + channel) as i8;
+        }
+    }
+    shuffles
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    use super::{channel_shuffles, Coefficients};
+
+    /// Converts 4 pixels per iteration.
+    ///
+    /// # Safety
+    /// The CPU must support SSE4.1.
+    #[target_feature(enable = "sse4.1")]
+    pub(super) unsafe fn convert_pixels_sse41(
+        rgb: &[u8],
+        bytes_per_pixel: usize,
+        coefficients: &Coefficients,
+        y: &mut [u8],
+        u: &mut [i32],
+        v: &mut [i32],
+    ) -> usize {
+        let shuffles = channel_shuffles(bytes_per_pixel)
+            .map(|shuffle| _mm_loadu_si128(shuffle.as_ptr() as *const __m128i));
+        let coefficient = |values: [i32; 3]| values.map(|value| _mm_set1_epi32(value));
+        let (cy, cu, cv) = (
+            coefficient(coefficients.y),
+            coefficient(coefficients.u),
+            coefficient(coefficients.v),
+        );
+        let round = _mm_set1_epi32(128);
+        let y_offset = _mm_set1_epi32(coefficients.y_offset);
+        let dot = |[r, g, b]: [__m128i; 3], c: [__m128i; 3]| {
+            _mm_add_epi32(
+                _mm_add_epi32(_mm_mullo_epi32(r, c[0]), _mm_mullo_epi32(g, c[1])),
+                _mm_mullo_epi32(b, c[2]),
+            )
+        };
+
+        let mut index = 0;
+        // Each iteration loads 16 bytes.
+        while index + 4 <= y.len() && index * bytes_per_pixel + 16 <= rgb.len() {
+            let pixels =
+                _mm_loadu_si128(rgb.as_ptr().add(index * bytes_per_pixel) as *const __m128i);
+            let channels = shuffles.map(|shuffle| _mm_shuffle_epi8(pixels, shuffle));
+
+            let luma = _mm_add_epi32(dot(channels, cy), round);
+            let luma = _mm_add_epi32(_mm_srai_epi32::<8>(luma), y_offset);
+            let luma = _mm_packus_epi32(luma, luma);
+            let luma = _mm_packus_epi16(luma, luma);
+            y[index..index + 4].copy_from_slice(&_mm_cvtsi128_si32(luma).to_ne_bytes());
+            _mm_storeu_si128(u.as_mut_ptr().add(index) as *mut __m128i, dot(channels, cu));
+            _mm_storeu_si128(v.as_mut_ptr().add(index) as *mut __m128i, dot(channels, cv));
+            index += 4;
+        }
+        index
+    }
+
+    /// Converts 8 pixels per iteration, as two groups of 4 pixels in the 128 bits lanes.
+    ///
+    /// # Safety
+    /// The CPU must support AVX2.
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn convert_pixels_avx2(
+        rgb: &[u8],
+        bytes_per_pixel: usize,
+        coefficients: &Coefficients,
+        y: &mut [u8],
+        u: &mut [i32],
+        v: &mut [i32],
+    ) -> usize {
+        let shuffles = channel_shuffles(bytes_per_pixel).map(|shuffle| {
+            _mm256_broadcastsi128_si256(_mm_loadu_si128(shuffle.as_ptr() as *const __m128i))
+        });
+        let coefficient = |values: [i32; 3]| values.map(|value| _mm256_set1_epi32(value));
+        let (cy, cu, cv) = (
+            coefficient(coefficients.y),
+            coefficient(coefficients.u),
+            coefficient(coefficients.v),
+        );
+        let round = _mm256_set1_epi32(128);
+        let y_offset = _mm256_set1_epi32(coefficients.y_offset);
+        let dot = |[r, g, b]: [__m256i; 3], c: [__m256i; 3]| {
+            _mm256_add_epi32(
+                _mm256_add_epi32(_mm256_mullo_epi32(r, c[0]), _mm256_mullo_epi32(g, c[1])),
+                _mm256_mullo_epi32(b, c[2]),
+            )
+        };
+
+        let mut index = 0;
+        // Each iteration loads 16 bytes at the first and the fifth pixel.
+        while index + 8 <= y.len() && (index + 4) * bytes_per_pixel + 16 <= rgb.len() {
+            let first = rgb.as_ptr().add(index * bytes_per_pixel);
+            let low = _mm_loadu_si128(first as *const __m128i);
+            let high = _mm_loadu_si128(first.add(4 * bytes_per_pixel) as *const __m128i);
+            let pixels = _mm256_inserti128_si256::<1>(_mm256_castsi128_si256(low), high);
+            let channels = shuffles.map(|shuffle| _mm256_shuffle_epi8(pixels, shuffle));
+
+            let luma = _mm256_add_epi32(dot(channels, cy), round);
+            let luma = _mm256_add_epi32(_mm256_srai_epi32::<8>(luma), y_offset);
+            // Packing works within each lane, leaving 4 pixels at the start of each lane.
+            let luma = _mm256_packus_epi32(luma, luma);
+            let luma = _mm256_packus_epi16(luma, luma);
+            let low = _mm_cvtsi128_si32(_mm256_castsi256_si128(luma));
+            let high = _mm_cvtsi128_si32(_mm256_extracti128_si256::<1>(luma));
+            y[index..index + 4].copy_from_slice(&low.to_ne_bytes());
+            y[index + 4..index + 8].copy_from_slice(&high.to_ne_bytes());
+            _mm256_storeu_si256(u.as_mut_ptr().add(index) as *mut __m256i, dot(channels, cu));
+            _mm256_storeu_si256(v.as_mut_ptr().add(index) as *mut __m256i, dot(channels, cv));
+            index += 8;
+        }
+        index
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64 {
+    use std::arch::aarch64::*;
+
+    use super::Coefficients;
+
+    /// Converts 8 pixels per iteration, as two groups of 4 pixels.
+    ///
+    /// # Safety
+    /// The CPU must support NEON.
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn convert_pixels_neon(
+        rgb: &[u8],
+        bytes_per_pixel: usize,
+        coefficients: &Coefficients,
+        y: &mut [u8],
+        u: &mut [i32],
+        v: &mut [i32],
+    ) -> usize {
+        let Coefficients {
+            y: cy,
+            u: cu,
+            v: cv,
+            y_offset,
+        } = *coefficients;
+        let dot = |[r, g, b]: [int32x4_t; 3], c: [i32; 3]| {
+            vmlaq_n_s32(vmlaq_n_s32(vmulq_n_s32(r, c[0]), g, c[1]), b, c[2])
+        };
+        let widen = |channel: uint8x8_t| {
+            let channel = vmovl_u8(channel);
+            [
+                vreinterpretq_s32_u32(vmovl_u16(vget_low_u16(channel))),
+                vreinterpretq_s32_u32(vmovl_u16(vget_high_u16(channel))),
+            ]
+        };
+
+        let mut index = 0;
+        while index + 8 <= y.len() && (index + 8) * bytes_per_pixel <= rgb.len() {
+            let first = rgb.as_ptr().add(index * bytes_per_pixel);
+            let [r, g, b] = match bytes_per_pixel {
+                3 => {
+                    let pixels = vld3_u8(first);
+                    [pixels.0, pixels.1, pixels.2]
+                }
+                _ => {
+                    let pixels = vld4_u8(first);
+                    [pixels.0, pixels.1, pixels.2]
+                }
+            };
+            let [r, g, b] = [widen(r), widen(g), widen(b)];
+            let mut luma = [vdup_n_u16(0); 2];
+            for half in 0..2 {
+                let channels = [r[half], g[half], b[half]];
+                let sum = vaddq_s32(dot(channels, cy), vdupq_n_s32(128));
+                let sum = vaddq_s32(vshrq_n_s32::<8>(sum), vdupq_n_s32(y_offset));
+                luma[half] = vqmovun_s32(sum);
+                let offset = index + 4 * half;
+                vst1q_s32(u.as_mut_ptr().add(offset), dot(channels, cu));
+                vst1q_s32(v.as_mut_ptr().add(offset), dot(channels, cv));
+            }
+            vst1_u8(
+                y.as_mut_ptr().add(index),
+                vqmovn_u16(vcombine_u16(luma[0], luma[1])),
+            );
+            index += 8;
+        }
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::convert::{ColorSpace, Matrix, Range};
+
+    type Kernel = fn(&[u8], usize, &Coefficients, &mut [u8], &mut [i32], &mut [i32]) -> usize;
+
+    /// Returns the vectorised kernels supported by the CPU.
+    fn kernels() -> Vec<(&'static str, Kernel)> {
+        #[allow(unused_mut)]
+        let mut kernels: Vec<(&'static str, Kernel)> = Vec::new();
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("sse4.1") {
+                kernels.push(("sse4.1", |rgb, bpp, c, y, u, v| unsafe {
+                    x86::convert_pixels_sse41(rgb, bpp, c, y, u, v)
+                }));
+            }
+            if is_x86_feature_detected!("avx2") {
+                kernels.push(("avx2", |rgb, bpp, c, y, u, v| unsafe {
+                    x86::convert_pixels_avx2(rgb, bpp, c, y, u, v)
+                }));
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            if std::arch::is_aarch64_feature_detected!("neon") {
+                kernels.push(("neon", |rgb, bpp, c, y, u, v| unsafe {
+                    aarch64::convert_pixels_neon(rgb, bpp, c, y, u, v)
+                }));
+            }
+        }
+        kernels
+    }
+
+    fn color_space() -> impl Strategy<Value = ColorSpace> {
+        let matrix = prop_oneof![
+            Just(Matrix::Bt601),
+            Just(Matrix::Bt709),
+            Just(Matrix::Bt2020)
+        ];
+        let range = prop_oneof![Just(Range::Limited), Just(Range::Full)];
+        (matrix, range).prop_map(|(matrix, range)| ColorSpace { matrix, range })
+    }
+
+    proptest! {
+        #[test]
+        fn kernels_match_scalar(
+            bytes_per_pixel in 3usize..=4,
+            rgb in proptest::collection::vec(any::<u8>(), 0..512),
+            color_space in color_space(),
+        ) {
+            let count = rgb.len() / bytes_per_pixel;
+            let rgb = &rgb[..count * bytes_per_pixel];
+            let coefficients = color_space.coefficients();
+            let mut expected = (vec![0; count], vec![0; count], vec![0; count]);
+            convert_pixels_scalar(
+                rgb,
+                bytes_per_pixel,
+                &coefficients,
+                &mut expected.0,
+                &mut expected.1,
+                &mut expected.2,
+            );
+
+            for (name, kernel) in kernels() {
+                let (mut y, mut u, mut v) = (vec![0; count], vec![0; count], vec![0; count]);
+                let done = kernel(rgb, bytes_per_pixel, &coefficients, &mut y, &mut u, &mut v);
+                prop_assert!(done <= count && (count < 16 || done > 0));
+                prop_assert_eq!(&y[..done], &expected.0[..done], "{}", name);
+                prop_assert_eq!(&u[..done], &expected.1[..done], "{}", name);
+                prop_assert_eq!(&v[..done], &expected.2[..done], "{}", name);
+            }
+
+            let (mut y, mut u, mut v) = (vec![0; count], vec![0; count], vec![0; count]);
+            convert_pixels(rgb, bytes_per_pixel, &coefficients, &mut y, &mut u, &mut v);
+            prop_assert_eq!((y, u, v), expected);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn short_chroma() {
+        let rgb = [0; 32 * 3];
+        let (mut y, mut u, mut v) = ([0; 32], [0; 32], [0; 4]);
+        let coefficients = ColorSpace::default().coefficients();
+        convert_pixels(&rgb, 3, &coefficients, &mut y, &mut u, &mut v);
+    }
+
+    #[test]
+    fn scalar_matches_pixel_conversion() {
+        let coefficients = ColorSpace::default().coefficients();
+        for value in 0..=255u8 {
+            for rgb in [
+                [value, 0, 0],
+                [0, value, 0],
+                [0, 0, value],
+                [value, 255 - value, 7],
+            ] {
+                let (mut y, mut u, mut v) = ([0], [0], [0]);
+                convert_pixels_scalar(&rgb, 3, &coefficients, &mut y, &mut u, &mut v);
+                let [r, g, b] = rgb.map(f32::from);
+                let pixel = crate::convert::convert_rgb_to_yuv420_pixel(r, g, b);
+                assert_eq!(y[0], pixel.y);
+                assert_eq!(super::super::scale_chroma(u[0], 1), pixel.u);
+                assert_eq!(super::super::scale_chroma(v[0], 1), pixel.v);
+            }
+        }
+    }
+}
@@ -0,0 +1,867 @@
+//! Safe wrapper around the libvpx VP8 and VP9 encoders.
+//!
+//! Frames are given as YUV420 buffers, and the compressed frames are returned
+//! as packets. VP9 also takes YUV444 frames, and can encode them losslessly.
+//! For a two-pass encoding, the first pass returns statistics
+//! packets instead, which are given back to the encoder of the last pass.
+
+use std::ffi::CStr;
+use std::fmt;
+use std::mem::MaybeUninit;
+use std::os::raw::{c_int, c_ulong};
+use std::{ptr, slice};
+
+use vpx_sys::vp8e_enc_control_id::*;
+use vpx_sys::vpx_codec_cx_pkt_kind::{VPX_CODEC_CX_FRAME_PKT, VPX_CODEC_STATS_PKT};
+use vpx_sys::*;
+
+use crate::convert::{ColorSpace, Matrix, PixelFormat, Range};
+use crate::mux::VideoCodec;
+
+/// Highest quantizer, and quality level, of VP8 and VP9.
+pub const MAX_QUANTIZER: u32 = 63;
+
+/// Speed setting of the VP9 encoder when none is given.
+const VP9_CPU_USED: i32 = 6;
+
+/// Number of threads used by the encoder when none is given.
+const THREADS: u32 = 8;
+
+/// Highest number of frames the encoder can look ahead.
+pub const MAX_LAG_IN_FRAMES: u32 = 25;
+
+/// How the encoder allocates bits to the frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateControl {
+    /// Variable bitrate, averaging a bitrate in kilobits per second.
+    Vbr { bitrate: u32 },
+    /// Constant bitrate in kilobits per second, for streaming.
+    Cbr { bitrate: u32 },
+    /// Constant quality, from 0 (best) to 63, without exceeding a bitrate in
+    /// kilobits per second.
+    ConstrainedQuality { quality: u32, bitrate: u32 },
+    /// Constant quality, from 0 (best) to 63.
+    Quality { quality: u32 },
+}
+
+impl RateControl {
+    fn end_usage(self) -> vpx_rc_mode {
+        match self {
+            RateControl::Vbr { .. } => vpx_rc_mode::VPX_VBR,
+            RateControl::Cbr { .. } => vpx_rc_mode::VPX_CBR,
+            RateControl::ConstrainedQuality { .. } => vpx_rc_mode::VPX_CQ,
+            RateControl::Quality { .. } => vpx_rc_mode::VPX_Q,
+        }
+    }
+
+    fn bitrate(self) -> Option<u32> {
+        match self {
+            RateControl::Vbr { bitrate }
+            | RateControl::Cbr { bitrate }
+            | RateControl::ConstrainedQuality { bitrate, .. } => Some(bitrate),
+            RateControl::Quality { .. } => None,
+        }
+    }
+
+    fn quality(self) -> Option<u32> {
+        match self {
+            RateControl::ConstrainedQuality { quality, .. } | RateControl::Quality { quality } => {
+                Some(quality)
+            }
+            RateControl::Vbr { .. } | RateControl::Cbr { .. } => None,
+        }
+    }
+}
+
+/// Pass of the encoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pass {
+    /// Single pass encoding.
+    Single,
+    /// First pass of a two-pass encoding, which only returns statistics.
+    First,
+    /// Last pass of a two-pass encoding, with the statistics of the first pass.
+    Last(Vec<u8>),
+}
+
+/// How long the encoder may spend on each frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deadline {
+    /// As fast as possible, for live encoding.
+    Realtime,
+    /// A balance between speed and quality.
+    Good,
+    /// The best quality, whatever the time it takes.
+    Best,
+}
+
+impl Deadline {
+    fn value(self) -> c_ulong {
+        (match self {
+            Deadline::Realtime => VPX_DL_REALTIME,
+            Deadline::Good => VPX_DL_GOOD_QUALITY,
+            Deadline::Best => VPX_DL_BEST_QUALITY,
+        }) as c_ulong
+    }
+}
+
+/// Speed and parallelism settings of the encoder.
+/// Settings which are not specified use the defaults of libvpx, unless noted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tuning {
+    /// Speed of the encoder, from -16 to 16 for VP8 and from -9 to 9 for VP9,
+    /// higher being faster. Defaults to 6 for VP9.
+    pub cpu_used: Option<i32>,
+    /// Time spent on each frame. Defaults to realtime for a single pass and
+    /// to good for a two-pass encoding, which cannot be realtime.
+    pub deadline: Option<Deadline>,
+    /// Number of threads. Defaults to 8.
+    pub threads: Option<u32>,
+    /// Base 2 logarithm of the number of tile columns, from 0 to 6, for VP9.
+    pub tile_columns: Option<u32>,
+    /// Base 2 logarithm of the number of tile rows, from 0 to 2, for VP9.
+    pub tile_rows: Option<u32>,
+    /// Encode the rows of tiles in parallel, for VP9. Defaults to true.
+    pub row_mt: Option<bool>,
+    /// Use hidden alternate reference frames, for VP9. VP8 returns them as
+    /// separate invisible frames, which the muxer cannot place.
+    pub auto_alt_ref: Option<bool>,
+    /// Number of frames the encoder looks ahead, from 0 to 25.
+    pub lag_in_frames: Option<u32>,
+    /// Lowest number of frames between two keyframes.
+    pub min_keyframe_interval: Option<u32>,
+    /// Highest number of frames between two keyframes.
+    pub max_keyframe_interval: Option<u32>,
+}
+
+/// Settings of the encoder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// The width of the video, which must be even.
+    pub width: u32,
+    /// The height of the video, which must be even.
+    pub height: u32,
+    /// The duration of a tick of the timestamps, as seconds numerator and denominator.
+    pub timebase: [i32; 2],
+    /// The codec of the video.
+    pub codec: VideoCodec,
+    /// How bits are allocated to the frames.
+    pub rate_control: RateControl,
+    /// The lowest quantizer used, from 0 to 63.
+    pub min_quantizer: Option<u32>,
+    /// The highest quantizer used, from 0 to 63.
+    pub max_quantizer: Option<u32>,
+    /// The pass of the encoding.
+    pub pass: Pass,
+    /// The layout of the frames. Only VP9 takes YUV422 and YUV444 frames.
+    pub pixel_format: PixelFormat,
+    /// The number of bits per sample: 8, or 10 and 12 with VP9. Frames with
+    /// more than 8 bits hold native endian 16 bits samples.
+    pub bit_depth: u32,
+    /// Encode the frames without loss, which is only available with VP9.
+    /// The rate control and the quantizers are then ignored.
+    pub lossless: bool,
+    /// The matrix and range of the frames, signalled in the VP9 bitstream.
+    pub color_space: ColorSpace,
+    /// Speed and parallelism of the encoder.
+    pub tuning: Tuning,
+    /// Place the keyframes every maximum keyframe interval instead of on scene
+    /// changes, so that streams encoded side by side share their keyframes.
+    pub fixed_keyframes: bool,
+}
+
+impl Config {
+    /// Returns the size in bytes of the frames given to the encoder.
+    pub fn frame_size(&self) -> usize {
+        let sample_size = if self.bit_depth > 8 { 2 } else { 1 };
+        sample_size * self.pixel_format.frame_size(self.width, self.height)
+    }
+}
+
+/// Error reported by libvpx, or invalid settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A packet returned by the encoder.
+#[derive(Debug, Clone, Copy)]
+pub enum Packet<'a> {
+    /// A compressed frame.
+    Frame(Frame<'a>),
+    /// Statistics of the first pass.
+    Stats(&'a [u8]),
+}
+
+/// A compressed frame.
+#[derive(Debug, Clone, Copy)]
+pub struct Frame<'a> {
+    /// Compressed data.
+    pub data: &'a [u8],
+    /// Whether the frame is a keyframe.
+    pub key: bool,
+    /// Presentation timestamp, in ticks.
+    pub pts: i64,
+}
+
+/// A VP8 or VP9 encoder.
+pub struct Encoder {
+    ctx: vpx_codec_ctx_t,
+    width: u32,
+    height: u32,
+    pixel_format: PixelFormat,
+    bit_depth: u32,
+    frame_size: usize,
+    deadline: c_ulong,
+    /// Number of frames between the keyframes forced by the encoder.
+    keyframe_interval: Option<i64>,
+    frame_count: i64,
+    // Read by libvpx during the last pass, so it lives as long as the context.
+    _stats: Vec<u8>,
+}
+
+impl Encoder {
+    /// Create an encoder.
+    ///
+    /// # Errors
+    /// If the settings are invalid or libvpx cannot create the encoder, an error is returned.
+    pub fn new(config: Config) -> Result<Self, Error> {
+        validate(&config)?;
+        let frame_size = config.frame_size();
+        // SAFETY: the interfaces are static descriptions of the codecs.
+        let interface = unsafe {
+            match config.codec {
+                VideoCodec::Vp8 => vpx_codec_vp8_cx(),
+                VideoCodec::Vp9 => vpx_codec_vp9_cx(),
+            }
+        };
+        if interface.is_null() {
+            return Err(Error(format!(
+                "{:?} is not supported by libvpx",
+                config.codec
+            )));
+        }
+
+        // SAFETY: the configuration is plain data, filled in by libvpx.
+        let mut cfg: vpx_codec_enc_cfg_t = unsafe { MaybeUninit::zeroed().assume_init() };
+        check(
+            unsafe { vpx_codec_enc_config_default(interface, &mut cfg, 0) },
+            None,
+            "Could not get the default encoder configuration",
+        )?;
+        cfg.g_w = config.width;
+        cfg.g_h = config.height;
+        cfg.g_profile = profile(config.pixel_format, config.bit_depth);
+        cfg.g_bit_depth = match config.bit_depth {
+            10 => vpx_bit_depth::VPX_BITS_10,
+            12 => vpx_bit_depth::VPX_BITS_12,
+            _ => vpx_bit_depth::VPX_BITS_8,
+        };
+        cfg.g_input_bit_depth = config.bit_depth;
+        cfg.g_timebase.num = config.timebase[0];
+        cfg.g_timebase.den = config.timebase[1];
+        let tuning = config.tuning;
+        cfg.g_threads = tuning.threads.unwrap_or(THREADS);
+        cfg.g_error_resilient = VPX_ERROR_RESILIENT_DEFAULT;
+        cfg.rc_end_usage = config.rate_control.end_usage();
+        if let Some(bitrate) = config.rate_control.bitrate() {
+            cfg.rc_target_bitrate = bitrate;
+        }
+        if let Some(min_quantizer) = config.min_quantizer {
+            cfg.rc_min_quantizer = min_quantizer;
+        }
+        if let Some(max_quantizer) = config.max_quantizer {
+            cfg.rc_max_quantizer = max_quantizer;
+        }
+        if let Some(lag_in_frames) = tuning.lag_in_frames {
+            cfg.g_lag_in_frames = lag_in_frames;
+        }
+        if let Some(min_keyframe_interval) = tuning.min_keyframe_interval {
+            cfg.kf_min_dist = min_keyframe_interval;
+        }
+        if let Some(max_keyframe_interval) = tuning.max_keyframe_interval {
+            cfg.kf_max_dist = max_keyframe_interval;
+        }
+        // Without automatic keyframes, libvpx only places those it is asked for.
+        let keyframe_interval = config.fixed_keyframes.then(|| {
+            cfg.kf_mode = vpx_kf_mode::VPX_KF_FIXED;
+            cfg.kf_max_dist.max(1) as i64
+        });
+        // Two-pass encoding is not available with the realtime deadline.
+        let (pass, deadline, stats) = match config.pass {
+            Pass::Single => (
+                vpx_enc_pass::VPX_RC_ONE_PASS,
+                Deadline::Realtime,
+                Vec::new(),
+            ),
+            Pass::First => (vpx_enc_pass::VPX_RC_FIRST_PASS, Deadline::Good, Vec::new()),
+            Pass::Last(stats) => (vpx_enc_pass::VPX_RC_LAST_PASS, Deadline::Good, stats),
+        };
+        let deadline = tuning.deadline.unwrap_or(deadline);
+        cfg.g_pass = pass;
+        cfg.rc_twopass_stats_in = vpx_fixed_buf {
+            buf: stats.as_ptr() as *mut _,
+            sz: stats.len() as _,
+        };
+
+        let flags = match config.bit_depth > 8 {
+            true => VPX_CODEC_USE_HIGHBITDEPTH as vpx_codec_flags_t,
+            false => 0,
+        };
+        // SAFETY: the context is initialized by libvpx, and only dropped once it is.
+        let mut ctx = unsafe { MaybeUninit::zeroed().assume_init() };
+        check(
+            unsafe {
+                vpx_codec_enc_init_ver(
+                    &mut ctx,
+                    interface,
+                    &cfg,
+                    flags,
+                    VPX_ENCODER_ABI_VERSION as c_int,
+                )
+            },
+            None,
+            "Could not initialize the encoder",
+        )?;
+        let mut encoder = Encoder {
+            ctx,
+            width: config.width,
+            height: config.height,
+            pixel_format: config.pixel_format,
+            bit_depth: config.bit_depth,
+            frame_size,
+            keyframe_interval,
+            frame_count: 0,
+            deadline: deadline.value(),
+            _stats: stats,
+        };
+        let cpu_used = match config.codec {
+            VideoCodec::Vp8 => tuning.cpu_used,
+            VideoCodec::Vp9 => Some(tuning.cpu_used.unwrap_or(VP9_CPU_USED)),
+        };
+        if let Some(cpu_used) = cpu_used {
+            encoder.control(VP8E_SET_CPUUSED, cpu_used)?;
+        }
+        if config.lossless {
+            encoder.control(VP9E_SET_LOSSLESS, 1)?;
+        }
+        if let Some(auto_alt_ref) = tuning.auto_alt_ref {
+            encoder.control(VP8E_SET_ENABLEAUTOALTREF, auto_alt_ref as c_int)?;
+        }
+        if config.codec == VideoCodec::Vp9 {
+            let (color_space, color_range) = vpx_color(config.color_space);
+            encoder.control(VP9E_SET_COLOR_SPACE, color_space as c_int)?;
+            encoder.control(VP9E_SET_COLOR_RANGE, color_range as c_int)?;
+            encoder.control(VP9E_SET_ROW_MT, tuning.row_mt.unwrap_or(true) as c_int)?;
+            if let Some(tile_columns) = tuning.tile_columns {
+                encoder.control(VP9E_SET_TILE_COLUMNS, tile_columns as c_int)?;
+            }
+            if let Some(tile_rows) = tuning.tile_rows {
+                encoder.control(VP9E_SET_TILE_ROWS, tile_rows as c_int)?;
+            }
+        }
+        if let Some(quality) = config.rate_control.quality() {
+            encoder.control(VP8E_SET_CQ_LEVEL, quality as c_int)?;
+        }
+        Ok(encoder)
+    }
+
+    /// Encode a frame in the pixel format of the encoder, shown at `pts` for
+    /// one tick, and return the packets ready.
+    ///
+    /// # Errors
+    /// If libvpx fails to encode the frame, an error is returned.
+    ///
+    /// # Panics
+    /// If the frame is smaller than a frame of the video size.
+    pub fn encode(&mut self, pts: i64, yuv: &[u8]) -> Result<Packets<'_>, Error> {
+        let high = self.bit_depth > 8;
+        assert!(yuv.len() >= self.frame_size);
+        let format = match (self.pixel_format, high) {
+            (PixelFormat::I420, false) => vpx_img_fmt::VPX_IMG_FMT_I420,
+            (PixelFormat::I422, false) => vpx_img_fmt::VPX_IMG_FMT_I422,
+            (PixelFormat::I444, false) => vpx_img_fmt::VPX_IMG_FMT_I444,
+            (PixelFormat::I420, true) => vpx_img_fmt::VPX_IMG_FMT_I42016,
+            (PixelFormat::I422, true) => vpx_img_fmt::VPX_IMG_FMT_I42216,
+            (PixelFormat::I444, true) => vpx_img_fmt::VPX_IMG_FMT_I44416,
+        };
+        // SAFETY: the image only describes the layout of the frame.
+        let mut image: vpx_image_t = unsafe { MaybeUninit::zeroed().assume_init() };
+        let wrapped = unsafe {
+            vpx_img_wrap(
+                &mut image,
+                format,
+                self.width,
+                self.height,
+                1,
+                yuv.as_ptr() as *mut _,
+            )
+        };
+        if wrapped.is_null() {
+            return Err(Error("Could not wrap the frame".to_string()));
+        }
+        image.bit_depth = self.bit_depth;
+        let keyframe = self
+            .keyframe_interval
+            .is_some_and(|interval| self.frame_count % interval == 0);
+        let flags = if keyframe { VPX_EFLAG_FORCE_KF } else { 0 };
+        self.encode_image(&image, pts, flags as vpx_enc_frame_flags_t)?;
+        self.frame_count += 1;
+        Ok(self.packets())
+    }
+
+    /// Flush the frames delayed by the encoder, and return the remaining packets.
+    /// Returns no packets once the encoder is empty.
+    ///
+    /// # Errors
+    /// If libvpx fails to encode the frames, an error is returned.
+    pub fn flush(&mut self) -> Result<Packets<'_>, Error> {
+        self.encode_image(ptr::null(), -1, 0)?;
+        Ok(self.packets())
+    }
+
+    fn encode_image(
+        &mut self,
+        image: *const vpx_image_t,
+        pts: i64,
+        flags: vpx_enc_frame_flags_t,
+    ) -> Result<(), Error> {
+        let result =
+            unsafe { vpx_codec_encode(&mut self.ctx, image, pts, 1, flags, self.deadline) };
+        check(result, Some(&mut self.ctx), "Could not encode a frame")
+    }
+
+    fn packets(&mut self) -> Packets<'_> {
+        Packets {
+            ctx: &mut self.ctx,
+            iter: ptr::null(),
+        }
+    }
+
+    fn control(&mut self, id: vp8e_enc_control_id, value: c_int) -> Result<(), Error> {
+        let result = unsafe { vpx_codec_control_(&mut self.ctx, id as c_int, value) };
+        check(
+            result,
+            Some(&mut self.ctx),
+            &format!("Could not set {:?}", id),
+        )
+    }
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        let result = unsafe { vpx_codec_destroy(&mut self.ctx) };
+        if result != VPX_CODEC_OK {
+            warn!("Could not destroy the encoder: {:?}", result);
+        }
+    }
+}
+
+/// Packets returned by the encoder after encoding or flushing frames.
+pub struct Packets<'a> {
+    ctx: &'a mut vpx_codec_ctx_t,
+    iter: vpx_codec_iter_t,
+}
+
+impl<'a> Iterator for Packets<'a> {
+    type Item = Packet<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // SAFETY: the packets are valid until the next call to the encoder,
+            // which needs the context borrowed by the iterator.
+            unsafe {
+                let packet = vpx_codec_get_cx_data(self.ctx, &mut self.iter);
+                if packet.is_null() {
+                    return None;
+                }
+                match (*packet).kind {
+                    VPX_CODEC_CX_FRAME_PKT => {
+                        let frame = &(*packet).data.frame;
+                        return Some(Packet::Frame(Frame {
+                            data: slice::from_raw_parts(frame.buf as *const u8, frame.sz as usize),
+                            key: frame.flags & VPX_FRAME_IS_KEY != 0,
+                            pts: frame.pts,
+                        }));
+                    }
+                    VPX_CODEC_STATS_PKT => {
+                        let stats = &(*packet).data.twopass_stats;
+                        return Some(Packet::Stats(slice::from_raw_parts(
+                            stats.buf as *const u8,
+                            stats.sz as usize,
+                        )));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Returns the VP9 profile of a pixel format and bit depth: profiles 1 and 3
+/// carry the 4:2:2 and 4:4:4 subsamplings, profiles 2 and 3 more than 8 bits.
+pub fn profile(pixel_format: PixelFormat, bit_depth: u32) -> u32 {
+    let high = (bit_depth > 8) as u32;
+    let subsampled = (pixel_format != PixelFormat::I420) as u32;
+    2 * high + subsampled
+}
+
+/// Returns the libvpx colour space and range of the frames. Without them, VP9
+/// keyframes say that the colour space is unknown and the range limited.
+fn vpx_color(color_space: ColorSpace) -> (vpx_color_space, vpx_color_range) {
+    let space = match color_space.matrix {
+        Matrix::Bt601 => vpx_color_space::VPX_CS_BT_601,
+        Matrix::Bt709 => vpx_color_space::VPX_CS_BT_709,
+        Matrix::Bt2020 => vpx_color_space::VPX_CS_BT_2020,
+    };
+    let range = match color_space.range {
+        Range::Limited => vpx_color_range::VPX_CR_STUDIO_RANGE,
+        Range::Full => vpx_color_range::VPX_CR_FULL_RANGE,
+    };
+    (space, range)
+}
+
+/// Check the settings which libvpx would reject without a meaningful message.
+fn validate(config: &Config) -> Result<(), Error> {
+    if !config.width.is_multiple_of(2) || !config.height.is_multiple_of(2) {
+        return Err(Error(format!(
+            "The video size must be even, not {}x{}",
+            config.width, config.height
+        )));
+    }
+    if ![8, 10, 12].contains(&config.bit_depth) {
+        return Err(Error(format!(
+            "The bit depth must be 8, 10 or 12, not {}",
+            config.bit_depth
+        )));
+    }
+    if config.codec != VideoCodec::Vp9 {
+        if config.bit_depth != 8 {
+            return Err(Error(format!(
+                "{} bits frames are only available with VP9",
+                config.bit_depth
+            )));
+        }
+        if config.pixel_format != PixelFormat::I420 {
+            return Err(Error(format!(
+                "{:?} frames are only available with VP9",
+                config.pixel_format
+            )));
+        }
+        if config.lossless {
+            return Err(Error(
+                "Lossless encoding is only available with VP9".to_string(),
+            ));
+        }
+    }
+    let quantizers = [
+        ("quality", config.rate_control.quality()),
+        ("minimum quantizer", config.min_quantizer),
+        ("maximum quantizer", config.max_quantizer),
+    ];
+    for (name, value) in quantizers {
+        if value.is_some_and(|value| value > MAX_QUANTIZER) {
+            return Err(Error(format!(
+                "The {} must be between 0 and {}",
+                name, MAX_QUANTIZER
+            )));
+        }
+    }
+    let min = config.min_quantizer.unwrap_or(0);
+    let max = config.max_quantizer.unwrap_or(MAX_QUANTIZER);
+    if min > max {
+        return Err(Error(format!(
+            "The minimum quantizer {} is above the maximum quantizer {}",
+            min, max
+        )));
+    }
+    if let Some(quality) = config.rate_control.quality() {
+        if quality < min || quality > max {
+            return Err(Error(format!(
+                "The quality {} is outside of the quantizer range {}-{}",
+                quality, min, max
+            )));
+        }
+    }
+    validate_tuning(config)
+}
+
+/// Check the speed and parallelism settings against the codec and the pass.
+fn validate_tuning(config: &Config) -> Result<(), Error> {
+    let tuning = &config.tuning;
+    let vp9 = config.codec == VideoCodec::Vp9;
+    let max_cpu_used = if vp9 { 9 } else { 16 };
+    if let Some(cpu_used) = tuning.cpu_used {
+        if cpu_used.abs() > max_cpu_used {
+            return Err(Error(format!(
+                "The cpu-used setting of {:?} must be between -{} and {}",
+                config.codec, max_cpu_used, max_cpu_used
+            )));
+        }
+    }
+    if tuning.deadline == Some(Deadline::Realtime) && config.pass != Pass::Single {
+        return Err(Error(
+            "Two-pass encoding is not available with the realtime deadline".to_string(),
+        ));
+    }
+    if tuning.threads == Some(0) {
+        return Err(Error("The encoder needs at least one thread".to_string()));
+    }
+    if !vp9 && (tuning.tile_columns.is_some() || tuning.tile_rows.is_some()) {
+        return Err(Error("Tiles are only available with VP9".to_string()));
+    }
+    if !vp9 && tuning.row_mt.is_some() {
+        return Err(Error(
+            "Row based multithreading is only available with VP9".to_string(),
+        ));
+    }
+    if !vp9 && tuning.auto_alt_ref == Some(true) {
+        return Err(Error(
+            "Alternate reference frames are only available with VP9".to_string(),
+        ));
+    }
+    let ranges = [
+        ("tile columns", tuning.tile_columns, 6),
+        ("tile rows", tuning.tile_rows, 2),
+        ("lag in frames", tuning.lag_in_frames, MAX_LAG_IN_FRAMES),
+    ];
+    for (name, value, max) in ranges {
+        if value.is_some_and(|value| value > max) {
+            return Err(Error(format!("The {} must be between 0 and {}", name, max)));
+        }
+    }
+    if let (Some(min), Some(max)) = (tuning.min_keyframe_interval, tuning.max_keyframe_interval) {
+        if min > max {
+            return Err(Error(format!(
+                "The minimum keyframe interval {} is above the maximum interval {}",
+                min, max
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Turn a libvpx result into an error, with the details of the context if any.
+fn check(
+    result: vpx_codec_err_t,
+    ctx: Option<&mut vpx_codec_ctx_t>,
+    what: &str,
+) -> Result<(), Error> {
+    if result == VPX_CODEC_OK {
+        return Ok(());
+    }
+    // SAFETY: libvpx returns static strings, or strings owned by the context.
+    let message = unsafe { CStr::from_ptr(vpx_codec_err_to_string(result)) };
+    let mut message = format!("{}: {}", what, message.to_string_lossy());
+    if let Some(ctx) = ctx {
+        let detail = unsafe { vpx_codec_error_detail(ctx) };
+        if !detail.is_null() {
+            let detail = unsafe { CStr::from_ptr(detail) };
+            message.push_str(&format!(" ({})", detail.to_string_lossy()));
+        }
+    }
+    Err(Error(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(rate_control: RateControl) -> Config {
+        Config {
+            width: 64,
+            height: 48,
+            timebase: [1, 30],
+            codec: VideoCodec::Vp9,
+            rate_control,
+            min_quantizer: None,
+            max_quantizer: None,
+            pass: Pass::Single,
+            pixel_format: PixelFormat::I420,
+            bit_depth: 8,
+            lossless: false,
+            color_space: ColorSpace::default(),
+            tuning: Tuning::default(),
+            fixed_keyframes: false,
+        }
+    }
+
+    #[test]
+    fn rate_controls() {
+        let cq = RateControl::ConstrainedQuality {
+            quality: 20,
+            bitrate: 1000,
+        };
+        assert_eq!(cq.end_usage(), vpx_rc_mode::VPX_CQ);
+        assert_eq!((cq.quality(), cq.bitrate()), (Some(20), Some(1000)));
+        let q = RateControl::Quality { quality: 31 };
+        assert_eq!(q.end_usage(), vpx_rc_mode::VPX_Q);
+        assert_eq!((q.quality(), q.bitrate()), (Some(31), None));
+        let cbr = RateControl::Cbr { bitrate: 500 };
+        assert_eq!(cbr.end_usage(), vpx_rc_mode::VPX_CBR);
+        assert_eq!((cbr.quality(), cbr.bitrate()), (None, Some(500)));
+    }
+
+    #[test]
+    fn color_spaces() {
+        assert_eq!(
+            vpx_color(ColorSpace::default()),
+            (
+                vpx_color_space::VPX_CS_BT_601,
+                vpx_color_range::VPX_CR_FULL_RANGE
+            )
+        );
+        let bt709 = ColorSpace {
+            matrix: Matrix::Bt709,
+            range: Range::Limited,
+        };
+        assert_eq!(
+            vpx_color(bt709),
+            (
+                vpx_color_space::VPX_CS_BT_709,
+                vpx_color_range::VPX_CR_STUDIO_RANGE
+            )
+        );
+        let bt2020 = ColorSpace {
+            matrix: Matrix::Bt2020,
+            range: Range::Full,
+        };
+        assert_eq!(vpx_color(bt2020).0, vpx_color_space::VPX_CS_BT_2020);
+    }
+
+    #[test]
+    fn invalid_settings() {
+        assert!(validate(&config(RateControl::Quality { quality: 31 })).is_ok());
+        assert!(validate(&config(RateControl::Quality { quality: 64 })).is_err());
+        let odd = Config {
+            width: 63,
+            ..config(RateControl::Vbr { bitrate: 1000 })
+        };
+        assert!(validate(&odd).is_err());
+        let inverted = Config {
+            min_quantizer: Some(40),
+            max_quantizer: Some(20),
+            ..config(RateControl::Vbr { bitrate: 1000 })
+        };
+        assert!(validate(&inverted).is_err());
+        let outside = Config {
+            max_quantizer: Some(20),
+            ..config(RateControl::Quality { quality: 31 })
+        };
+        assert!(validate(&outside).is_err());
+        for vp8 in [
+            Config {
+                codec: VideoCodec::Vp8,
+                lossless: true,
+                ..config(RateControl::Quality { quality: 31 })
+            },
+            Config {
+                codec: VideoCodec::Vp8,
+                pixel_format: PixelFormat::I444,
+                ..config(RateControl::Quality { quality: 31 })
+            },
+            Config {
+                codec: VideoCodec::Vp8,
+                bit_depth: 10,
+                ..config(RateControl::Quality { quality: 31 })
+            },
+        ] {
+            assert!(validate(&vp8).is_err());
+        }
+    }
+
+    #[test]
+    fn profiles() {
+        assert_eq!(profile(PixelFormat::I420, 8), 0);
+        assert_eq!(profile(PixelFormat::I422, 8), 1);
+        assert_eq!(profile(PixelFormat::I420, 10), 2);
+        assert_eq!(profile(PixelFormat::I444, 12), 3);
+        let depth = |bit_depth| Config {
+            bit_depth,
+            pixel_format: PixelFormat::I422,
+            ..config(RateControl::Quality { quality: 31 })
+        };
+        assert!(validate(&depth(10)).is_ok());
+        assert!(validate(&depth(12)).is_ok());
+        assert!(validate(&depth(9)).is_err());
+        assert!(validate(&depth(16)).is_err());
+    }
+
+    #[test]
+    fn invalid_tuning() {
+        let tuned = |codec, pass, tuning| Config {
+            codec,
+            pass,
+            tuning,
+            ..config(RateControl::Quality { quality: 31 })
+        };
+        let vp9 = |tuning| tuned(VideoCodec::Vp9, Pass::Single, tuning);
+        let vp8 = |tuning| tuned(VideoCodec::Vp8, Pass::Single, tuning);
+        let all = Tuning {
+            cpu_used: Some(-9),
+            deadline: Some(Deadline::Best),
+            threads: Some(4),
+            tile_columns: Some(6),
+            tile_rows: Some(2),
+            row_mt: Some(false),
+            auto_alt_ref: Some(true),
+            lag_in_frames: Some(25),
+            min_keyframe_interval: Some(10),
+            max_keyframe_interval: Some(10),
+        };
+        assert!(validate(&vp9(all)).is_ok());
+        for tuning in [
+            Tuning {
+                cpu_used: Some(10),
+                ..all
+            },
+            Tuning {
+                threads: Some(0),
+                ..all
+            },
+            Tuning {
+                tile_columns: Some(7),
+                ..all
+            },
+            Tuning {
+                lag_in_frames: Some(26),
+                ..all
+            },
+            Tuning {
+                min_keyframe_interval: Some(11),
+                ..all
+            },
+        ] {
+            assert!(validate(&vp9(tuning)).is_err(), "{:?}", tuning);
+        }
+
+        let cpu_used = Tuning {
+            cpu_used: Some(-16),
+            ..Tuning::default()
+        };
+        assert!(validate(&vp8(cpu_used)).is_ok());
+        let tiles = Tuning {
+            tile_columns: Some(1),
+            ..Tuning::default()
+        };
+        assert!(validate(&vp8(tiles)).is_err());
+        let alt_ref = |auto_alt_ref| Tuning {
+            auto_alt_ref: Some(auto_alt_ref),
+            ..Tuning::default()
+        };
+        assert!(validate(&vp8(alt_ref(true))).is_err());
+        assert!(validate(&vp8(alt_ref(false))).is_ok());
+        assert!(validate(&vp9(alt_ref(true))).is_ok());
+        let realtime = Tuning {
+            deadline: Some(Deadline::Realtime),
+            ..Tuning::default()
+        };
+        assert!(validate(&tuned(VideoCodec::Vp9, Pass::First, realtime)).is_err());
+    }
+}
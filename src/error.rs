@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use thiserror::Error as ErrorDerive;
 
 #[derive(ErrorDerive, Debug)]
@@ -6,11 +7,29 @@ pub enum Error {
     IO(#[from] std::io::Error),
 
     #[error("Encoder error: `{0}`")]
-    Encoder(#[from] vpx_encode::Error),
+    Encoder(#[from] crate::encoder::Error),
 
     #[error("Encoder error: `{0}`")]
     EncoderCustom(String),
 
+    #[error("Image error: `{0}`")]
+    Image(#[from] image::ImageError),
+
+    #[error("Could not decode `{}`: `{source}`", .path.display())]
+    Decode {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+
+    #[error("Input error: `{0}`")]
+    Input(String),
+
+    #[error("Manifest error: `{0}`")]
+    Manifest(String),
+
     #[error("No images found in: `{0}`")]
     NoImages(String),
+
+    #[error("Output file already exists: `{0}`")]
+    OutputExists(String),
 }
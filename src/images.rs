@@ -1,47 +1,1195 @@
 //! Contains all functions related to images.
 
-use image::DynamicImage;
-use std::fs;
-use std::io;
-use std::path::Path;
-
-/// Get all images from a given directory.
-/// Images are sorted by name.
-///  
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::io::Reader;
+use image::{AnimationDecoder, DynamicImage, ImageFormat};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::iter::{self, Peekable};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::input::{resolve_inputs, Input};
+use crate::manifest::Manifest;
+use crate::settings::InputSettings;
+use crate::sort::sort_paths;
+
+/// Maximum number of decoded frames of an animation waiting to be read.
+const ANIMATION_LOOK_AHEAD: usize = 2;
+
+/// Get the paths of all images from a given directory.
+///
+/// Files are kept if their format, detected from their content, can be decoded
+/// and is allowed by the settings. Files which cannot be read are skipped with a
+/// warning. Subdirectories are read up to the depth limit
+/// of the settings. Symbolic links to directories are not followed.
+///
 /// # Arguments
 /// * `path` - The path to the directory.
+/// * `settings` - The input settings, giving the depth, the filters and the order of the images.
 ///
 /// # Returns
-/// A vector of paths to all images in the directory as [DynamicImage].
+/// A vector of paths to all images in the directory.
 ///
 /// # Example
 /// ```
-/// use images::get_images;
-/// let images = get_images("src/images");
+/// use img_to_video::images::get_image_paths;
+/// use img_to_video::settings::InputSettings;
+/// let paths = get_image_paths(&"src/images", &InputSettings::default());
 /// ```
 ///
 /// # Errors
-/// If the directory does not exist, or if it is not a directory, or if it is empty, an error is returned.
+/// If the directory does not exist, if it is not a directory, if a filter is
+/// not a valid glob pattern or if a format is unknown, an error is returned.
 ///
-pub fn get_images<P: AsRef<Path>>(path: &P) -> Result<Vec<DynamicImage>, io::Error> {
-    let mut images: Vec<DynamicImage> = Vec::new();
-    let mut entries = fs::read_dir(path)?
-        .map(|res| res.map(|e| e))
-        .collect::<Result<Vec<_>, io::Error>>()?;
-
-    entries.sort_by_key(|a| a.file_name());
+pub fn get_image_paths<P: AsRef<Path>>(
+    path: &P,
+    settings: &InputSettings,
+) -> Result<Vec<PathBuf>, Error> {
+    let filter = PathFilter::new(&settings.include, &settings.exclude)?;
+    let formats = FormatFilter::new(&settings.formats)?;
+    let root = path.as_ref();
+    let mut paths: Vec<PathBuf> = Vec::new();
+    collect_image_paths(
+        root,
+        root,
+        0,
+        settings.depth_limit(),
+        &filter,
+        &formats,
+        &mut paths,
+    )?;
+    sort_paths(&mut paths, settings.sort, settings.reverse);
+    Ok(paths)
+}
 
+/// Add the images of `directory` and its subdirectories to `paths`.
+/// Only the input directory itself must be readable: the subdirectories and
+/// entries which cannot be read are skipped with a warning.
+fn collect_image_paths(
+    root: &Path,
+    directory: &Path,
+    depth: usize,
+    depth_limit: Option<usize>,
+    filter: &PathFilter,
+    formats: &FormatFilter,
+    paths: &mut Vec<PathBuf>,
+) -> Result<(), io::Error> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) if depth > 0 => {
+            warn!("Skipped {}: {}", directory.display(), err);
+            return Ok(());
+        }
+        Err(err) => return Err(err),
+    };
     for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                warn!("Skipped an entry of {}: {}", directory.display(), err);
+                continue;
+            }
+        };
         let path = entry.path();
-        let ext = match path.extension() {
-            Some(e) => e,
-            None => continue,
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(err) => {
+                warn!("Skipped {}: {}", path.display(), err);
+                continue;
+            }
         };
-        if ext == "jpg" || ext == "png" || ext == "jpeg" {
-            let image = image::open(&path).unwrap();
-            images.push(image);
+        if file_type.is_dir() {
+            if depth_limit.is_none_or(|limit| depth < limit) && !filter.excludes(relative) {
+                let depth = depth + 1;
+                collect_image_paths(root, &path, depth, depth_limit, filter, formats, paths)?;
+            }
+            continue;
+        }
+        if filter.includes(relative) && formats.keeps(&path) {
             debug!("Added {}", path.display());
+            paths.push(path);
         }
     }
-    Ok(images)
+    Ok(())
+}
+
+/// Returns the format of an image file, if it can be decoded.
+/// The format is detected from the content of the file, or from its extension
+/// for formats without a signature, like TGA.
+pub fn detect_format<P: AsRef<Path>>(path: P) -> Result<Option<ImageFormat>, io::Error> {
+    let reader = image_reader(path.as_ref())?;
+    Ok(reader.format().filter(|format| format.reading_enabled()))
+}
+
+/// Open an image file with the format detected from its content.
+fn image_reader(path: &Path) -> Result<Reader<BufReader<File>>, io::Error> {
+    let mut reader = Reader::new(BufReader::new(File::open(path)?)).with_guessed_format()?;
+    if reader.format().is_none() {
+        // TGA files have no signature, so only their extension tells them apart.
+        if let Ok(ImageFormat::Tga) = ImageFormat::from_path(path) {
+            reader.set_format(ImageFormat::Tga);
+        }
+    }
+    Ok(reader)
+}
+
+/// Image formats accepted from the inputs.
+pub(crate) struct FormatFilter {
+    allowed: Vec<ImageFormat>,
+}
+
+impl FormatFilter {
+    /// Create a filter from format names, like `png` or `jpeg`.
+    /// An empty list accepts every format that can be decoded.
+    pub(crate) fn new(names: &[String]) -> Result<Self, Error> {
+        let allowed = names
+            .iter()
+            .map(|name| {
+                ImageFormat::from_extension(name.trim())
+                    .ok_or_else(|| Error::Input(format!("unknown image format `{}`", name)))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(FormatFilter { allowed })
+    }
+
+    /// Returns whether the file at `path` is an image in an accepted format.
+    pub(crate) fn accepts(&self, path: &Path) -> Result<bool, io::Error> {
+        Ok(match detect_format(path)? {
+            Some(format) => self.allowed.is_empty() || self.allowed.contains(&format),
+            None => false,
+        })
+    }
+
+    /// Returns whether the file at `path` is an image in an accepted format, like
+    /// [FormatFilter::accepts], skipping files which cannot be read with a warning.
+    pub(crate) fn keeps(&self, path: &Path) -> bool {
+        self.accepts(path).unwrap_or_else(|err| {
+            warn!("Skipped {}: {}", path.display(), err);
+            false
+        })
+    }
+}
+
+/// Include and exclude glob patterns of the images of a directory.
+struct PathFilter {
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+}
+
+impl PathFilter {
+    fn new(include: &[String], exclude: &[String]) -> Result<Self, Error> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    glob::Pattern::new(pattern)
+                        .map_err(|err| Error::Input(format!("{}: {}", pattern, err)))
+                })
+                .collect::<Result<Vec<_>, Error>>()
+        };
+        Ok(PathFilter {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    /// Returns whether the image at `relative` is kept.
+    fn includes(&self, relative: &Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| pattern_matches(p, relative)))
+            && !self.excludes(relative)
+    }
+
+    /// Returns whether the image or directory at `relative` is skipped.
+    fn excludes(&self, relative: &Path) -> bool {
+        self.exclude.iter().any(|p| pattern_matches(p, relative))
+    }
+}
+
+/// Patterns without a separator match the file name, others the whole relative path.
+fn pattern_matches(pattern: &glob::Pattern, relative: &Path) -> bool {
+    if pattern.as_str().contains('/') {
+        return pattern.matches_path_with(
+            relative,
+            glob::MatchOptions {
+                require_literal_separator: true,
+                ..glob::MatchOptions::new()
+            },
+        );
+    }
+    relative
+        .file_name()
+        .is_some_and(|name| pattern.matches(&name.to_string_lossy()))
+}
+
+/// A single frame to encode, either still on disk or already in memory.
+#[derive(Clone)]
+pub enum FrameInput {
+    /// An image file, decoded when the frame is processed.
+    Path(PathBuf),
+    /// An already decoded image.
+    Image(DynamicImage),
+}
+
+impl FrameInput {
+    /// Returns the dimensions of the frame without decoding it.
+    /// If `auto_orient` is set, the dimensions are those of the image once rotated
+    /// according to its EXIF orientation.
+    ///
+    /// # Errors
+    /// If the file cannot be read or its header is invalid, [Error::Decode] is returned.
+    pub fn dimensions(&self, auto_orient: bool) -> Result<(u32, u32), Error> {
+        match self {
+            FrameInput::Path(path) => {
+                let (width, height) = image_reader(path)
+                    .map_err(image::ImageError::from)
+                    .and_then(|reader| reader.into_dimensions())
+                    .map_err(|source| decode_error(path, source))?;
+                match auto_orient && Orientation::read(path).swaps_dimensions() {
+                    true => Ok((height, width)),
+                    false => Ok((width, height)),
+                }
+            }
+            FrameInput::Image(image) => Ok((image.width(), image.height())),
+        }
+    }
+
+    /// Decode the frame if needed.
+    /// If `auto_orient` is set, the image is rotated and flipped according to its
+    /// EXIF orientation.
+    ///
+    /// # Errors
+    /// If the file cannot be read or decoded, [Error::Decode] is returned.
+    pub fn load(self, auto_orient: bool) -> Result<DynamicImage, Error> {
+        match self {
+            FrameInput::Path(path) => {
+                let image = image_reader(&path)
+                    .map_err(image::ImageError::from)
+                    .and_then(|reader| reader.decode())
+                    .map_err(|source| decode_error(&path, source))?;
+                debug!("Decoded {}", path.display());
+                match auto_orient {
+                    true => Ok(Orientation::read(&path).apply(image)),
+                    false => Ok(image),
+                }
+            }
+            FrameInput::Image(image) => Ok(image),
+        }
+    }
+}
+
+/// Orientation of a photo, as given by its EXIF Orientation tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Orientation(u32);
+
+impl Orientation {
+    /// Read the orientation of an image file.
+    /// Files without EXIF data or without a valid tag are upright.
+    fn read(path: &Path) -> Self {
+        let orientation = File::open(path)
+            .ok()
+            .and_then(|file| {
+                exif::Reader::new()
+                    .read_from_container(&mut BufReader::new(file))
+                    .ok()
+            })
+            .and_then(|exif| {
+                exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                    .and_then(|field| field.value.get_uint(0))
+            })
+            .filter(|orientation| (1..=8).contains(orientation))
+            .unwrap_or(1);
+        if orientation != 1 {
+            debug!(
+                "{} has the EXIF orientation {}",
+                path.display(),
+                orientation
+            );
+        }
+        Orientation(orientation)
+    }
+
+    /// Returns whether the image is rotated by a quarter turn.
+    fn swaps_dimensions(self) -> bool {
+        self.0 >= 5
+    }
+
+    /// Rotate and flip an image so it is upright.
+    fn apply(self, image: DynamicImage) -> DynamicImage {
+        match self.0 {
+            2 => image.fliph(),
+            3 => image.rotate180(),
+            4 => image.flipv(),
+            5 => image.rotate90().fliph(),
+            6 => image.rotate90(),
+            7 => image.rotate270().fliph(),
+            8 => image.rotate270(),
+            _ => image,
+        }
+    }
+}
+
+fn decode_error(path: &Path, source: image::ImageError) -> Error {
+    Error::Decode {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+/// A frame of a [FrameSource], with how long it is shown.
+#[derive(Clone)]
+pub struct Frame {
+    /// The image of the frame.
+    pub input: FrameInput,
+    /// How long the frame is shown.
+    /// If not specified, the duration given by the frame rate is used.
+    pub duration: Option<Duration>,
+}
+
+impl From<FrameInput> for Frame {
+    fn from(input: FrameInput) -> Self {
+        Frame {
+            input,
+            duration: None,
+        }
+    }
+}
+
+/// Ordered source of frames to encode.
+pub struct FrameSource {
+    origin: String,
+    len: Option<usize>,
+    frames: Peekable<Box<dyn Iterator<Item = Frame> + Send>>,
+    /// The frames before animations and repeats are expanded, to read them again.
+    replay: Option<Vec<ListedFrame>>,
+}
+
+/// A frame of a source which can be replayed, before it is expanded.
+#[derive(Clone)]
+struct ListedFrame {
+    frame: Frame,
+    /// The number of frames of the animation, if the frame is one.
+    length: Option<usize>,
+    /// How many times the frame is shown in a row.
+    repeat: u32,
+}
+
+impl FrameSource {
+    fn new<I>(origin: &str, frames: I) -> Self
+    where
+        I: Iterator<Item = Frame> + Send + 'static,
+    {
+        let len = match frames.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(upper),
+            _ => None,
+        };
+        let frames: Box<dyn Iterator<Item = Frame> + Send> = Box::new(frames);
+        FrameSource {
+            origin: origin.to_string(),
+            len,
+            frames: frames.peekable(),
+            replay: None,
+        }
+    }
+
+    /// Create a source from all images of a directory, in the order given by the settings.
+    ///
+    /// # Errors
+    /// If the directory cannot be read, an error is returned.
+    pub fn from_directory<P: AsRef<Path>>(
+        path: P,
+        settings: &InputSettings,
+    ) -> Result<Self, Error> {
+        let paths = get_image_paths(&path, settings)?;
+        let mut source = Self::from_paths(paths);
+        source.origin = path.as_ref().display().to_string();
+        Ok(source)
+    }
+
+    /// Create a source from the images of several inputs, one input after the other.
+    /// See [Input] for the kinds of inputs.
+    ///
+    /// # Errors
+    /// If an input cannot be resolved, an error is returned.
+    pub fn from_inputs(inputs: &[Input], settings: &InputSettings) -> Result<Self, Error> {
+        let paths = resolve_inputs(inputs, settings)?;
+        let mut source = Self::from_paths(paths);
+        source.origin = inputs
+            .iter()
+            .map(|input| input.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        Ok(source)
+    }
+
+    /// Create a source from the images listed in a timing manifest.
+    ///
+    /// # Errors
+    /// If the manifest cannot be read or is invalid, an error is returned.
+    pub fn from_manifest<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let frames = Manifest::open(&path)?.into_frames()?;
+        let mut source = Self::from_frame_list(frames.into_iter());
+        source.origin = path.as_ref().display().to_string();
+        Ok(source)
+    }
+
+    /// Create a source from a list of image files, in presentation order.
+    pub fn from_paths(paths: Vec<PathBuf>) -> Self {
+        Self::from_frame_list(
+            paths
+                .into_iter()
+                .map(|path| (FrameInput::Path(path).into(), 1)),
+        )
+    }
+
+    /// Create a source from already decoded images, in presentation order.
+    pub fn from_images<I>(images: I) -> Self
+    where
+        I: IntoIterator<Item = DynamicImage>,
+        I::IntoIter: Send + 'static,
+    {
+        Self::new(
+            "frame source",
+            images
+                .into_iter()
+                .map(|image| FrameInput::Image(image).into()),
+        )
+    }
+
+    /// Create a source from frames with their own durations, in presentation order.
+    ///
+    /// Animated GIF, PNG and WebP files are expanded into their frames, shown for
+    /// their own delays. As in a manifest, an animation cannot be given its own
+    /// duration: the duration is ignored with a warning. Since the number of
+    /// frames of an animation is only known once it is read, the frame count of
+    /// the source is unknown.
+    pub fn from_frames<I>(frames: I) -> Self
+    where
+        I: IntoIterator<Item = Frame>,
+        I::IntoIter: Send + 'static,
+    {
+        Self::new(
+            "frame source",
+            frames.into_iter().flat_map(|frame| {
+                let length = animation_length(&frame);
+                if let (FrameInput::Path(path), Some(_), Some(_)) =
+                    (&frame.input, frame.duration, length)
+                {
+                    warn!(
+                        "{} is an animation, its frames keep their own delays instead of a duration",
+                        path.display()
+                    );
+                }
+                expand_animation(frame, length)
+            }),
+        )
+    }
+
+    /// Create a source from a list of frames with how many times each one is
+    /// shown in a row, which can be replayed.
+    /// The headers of the animations are read to count their frames.
+    fn from_frame_list(frames: impl Iterator<Item = (Frame, u32)>) -> Self {
+        Self::from_counted_frames(
+            frames
+                .map(|(frame, repeat)| ListedFrame {
+                    length: animation_length(&frame),
+                    frame,
+                    repeat,
+                })
+                .collect(),
+        )
+    }
+
+    /// Create a source from a list of frames with the number of frames of the
+    /// animations among them. Repeated frames are expanded as they are read.
+    fn from_counted_frames(frames: Vec<ListedFrame>) -> Self {
+        let len = frames
+            .iter()
+            .map(|listed| listed.length.unwrap_or(1) * listed.repeat as usize)
+            .sum();
+        let mut source = Self::new(
+            "frame source",
+            frames.clone().into_iter().flat_map(|listed| {
+                iter::repeat_n((listed.frame, listed.length), listed.repeat as usize)
+                    .flat_map(|(frame, length)| expand_animation(frame, length))
+            }),
+        );
+        source.len = Some(len);
+        source.replay = Some(frames);
+        source
+    }
+
+    /// Returns a new source reading the same frames from the start, for
+    /// encodings needing several passes.
+    /// Returns `None` if the frames can only be read once, like the ones given
+    /// by an iterator.
+    pub fn replay(&self) -> Option<FrameSource> {
+        let mut source = Self::from_counted_frames(self.replay.clone()?);
+        source.origin = self.origin.clone();
+        Some(source)
+    }
+
+    /// Returns a description of where the frames come from.
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    /// Returns the number of frames, if known in advance.
+    /// The frames of an animation are counted from its headers, so a damaged
+    /// animation can give fewer frames.
+    pub fn frame_count(&self) -> Option<usize> {
+        self.len
+    }
+
+    /// Returns the first frame without consuming it.
+    pub fn peek(&mut self) -> Option<&Frame> {
+        self.frames.peek()
+    }
+}
+
+/// Returns the number of frames a frame is expanded into if it is an animated
+/// image file, or `None` otherwise.
+fn animation_length(frame: &Frame) -> Option<usize> {
+    match &frame.input {
+        FrameInput::Path(path) => animation_frame_count(path),
+        FrameInput::Image(_) => None,
+    }
+}
+
+/// Returns the frames of an animated image file, or the frame itself if it is
+/// not an animation, as given by [animation_length].
+fn expand_animation(frame: Frame, length: Option<usize>) -> Box<dyn Iterator<Item = Frame> + Send> {
+    let animation = match (&frame.input, length) {
+        (FrameInput::Path(path), Some(_)) => animation_frames(path),
+        _ => None,
+    };
+    match animation {
+        Some(animation) => Box::new(animation),
+        None => Box::new(iter::once(frame)),
+    }
+}
+
+/// Returns the frames of an animated image file, with their delays. The frames
+/// are decoded on their own thread, at most [ANIMATION_LOOK_AHEAD] frames ahead
+/// of the reader, so long animations are never fully decoded in memory.
+///
+/// Returns `None` if the file is not an animation, or if it cannot be read, in
+/// which case the error is reported when the file is decoded as a still image.
+/// Frames without a delay are shown for the duration given by the frame rate.
+fn animation_frames(path: &Path) -> Option<impl Iterator<Item = Frame> + Send> {
+    let (sender, receiver) = mpsc::sync_channel(ANIMATION_LOOK_AHEAD);
+    let decoded_path = path.to_path_buf();
+    thread::spawn(move || decode_animation(&decoded_path, sender));
+    // An animation whose second frame cannot be decoded is read as a still image.
+    let first: Vec<Frame> = receiver.iter().take(2).collect();
+    if first.len() < 2 {
+        return None;
+    }
+    Some(first.into_iter().chain(receiver))
+}
+
+/// Returns the number of frames of an animated GIF, PNG or WebP file, or `None`
+/// if the file is a still image, is in another format or cannot be read.
+///
+/// Only the headers of the blocks of the file are read, so telling a still image
+/// from an animation is much cheaper than decoding it.
+pub(crate) fn animation_frame_count(path: &Path) -> Option<usize> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let mut signature = [0; 12];
+    reader.read_exact(&mut signature).ok()?;
+    let count = if signature.starts_with(b"\x89PNG\r\n\x1a\n") {
+        png_frame_count(&mut reader).ok()?
+    } else if signature.starts_with(b"GIF8") {
+        gif_frame_count(&mut reader)
+    } else if signature.starts_with(b"RIFF") && signature.ends_with(b"WEBP") {
+        webp_frame_count(&mut reader).ok()?
+    } else {
+        None
+    };
+    count.filter(|count| *count > 1)
+}
+
+/// Returns the number of frames given by the animation control chunk of a PNG
+/// file, which comes before the image data.
+fn png_frame_count(reader: &mut BufReader<File>) -> io::Result<Option<usize>> {
+    reader.seek(SeekFrom::Start(8))?;
+    loop {
+        let mut header = [0; 8];
+        reader.read_exact(&mut header)?;
+        match &header[4..] {
+            b"acTL" => {
+                let mut frames = [0; 4];
+                reader.read_exact(&mut frames)?;
+                return Ok(Some(u32::from_be_bytes(frames) as usize));
+            }
+            b"IDAT" | b"IEND" => return Ok(None),
+            _ => {
+                let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+                // The data of the chunk is followed by its CRC.
+                reader.seek_relative(i64::from(length) + 4)?;
+            }
+        }
+    }
+}
+
+/// Returns the number of images of a GIF file. The images of a truncated file
+/// are counted up to the first incomplete one.
+fn gif_frame_count(reader: &mut BufReader<File>) -> Option<usize> {
+    let mut count = 0;
+    let _ = count_gif_images(reader, &mut count);
+    Some(count)
+}
+
+fn count_gif_images(reader: &mut BufReader<File>, count: &mut usize) -> io::Result<()> {
+    let color_table_size = |flags: u8| match flags & 0x80 {
+        0 => 0,
+        _ => 3 << ((flags & 0x07) + 1),
+    };
+    // The logical screen descriptor follows the 6 bytes of the signature.
+    reader.seek(SeekFrom::Start(6))?;
+    let mut screen = [0; 7];
+    reader.read_exact(&mut screen)?;
+    reader.seek_relative(color_table_size(screen[4]))?;
+    loop {
+        let mut introducer = [0; 1];
+        reader.read_exact(&mut introducer)?;
+        match introducer[0] {
+            // Extension, with its label before its data.
+            0x21 => {
+                reader.seek_relative(1)?;
+                skip_gif_sub_blocks(reader)?;
+            }
+            // Image, with its descriptor and the minimum code size before its data.
+            0x2C => {
+                let mut descriptor = [0; 9];
+                reader.read_exact(&mut descriptor)?;
+                reader.seek_relative(color_table_size(descriptor[8]) + 1)?;
+                skip_gif_sub_blocks(reader)?;
+                *count += 1;
+            }
+            // Trailer.
+            _ => return Ok(()),
+        }
+    }
+}
+
+fn skip_gif_sub_blocks(reader: &mut BufReader<File>) -> io::Result<()> {
+    loop {
+        let mut size = [0; 1];
+        reader.read_exact(&mut size)?;
+        if size[0] == 0 {
+            return Ok(());
+        }
+        reader.seek_relative(size[0].into())?;
+    }
+}
+
+/// Returns the number of animation frames of a WebP file, or `None` if its
+/// extended header does not flag an animation.
+fn webp_frame_count(reader: &mut BufReader<File>) -> io::Result<Option<usize>> {
+    let mut count = 0;
+    loop {
+        let mut header = [0; 8];
+        match reader.read_exact(&mut header) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(Some(count)),
+            result => result?,
+        }
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        // Chunks are padded to an even size.
+        let size = i64::from(size) + i64::from(size & 1);
+        match &header[..4] {
+            b"VP8X" => {
+                let mut flags = [0; 1];
+                reader.read_exact(&mut flags)?;
+                if flags[0] & 0x02 == 0 {
+                    return Ok(None);
+                }
+                reader.seek_relative(size - 1)?;
+            }
+            b"VP8 " | b"VP8L" => return Ok(None),
+            b"ANMF" => {
+                count += 1;
+                reader.seek_relative(size)?;
+            }
+            _ => reader.seek_relative(size)?,
+        }
+    }
+}
+
+/// Decode the frames of an animated image file and send them one at a time,
+/// until the receiver is dropped.
+fn decode_animation(path: &Path, sender: SyncSender<Frame>) {
+    let Some(frames) = animation_decoder(path) else {
+        return;
+    };
+    let mut count = 0;
+    for frame in frames {
+        match frame {
+            Ok(frame) => {
+                let delay = Duration::from(frame.delay());
+                let frame = Frame {
+                    input: FrameInput::Image(DynamicImage::ImageRgba8(frame.into_buffer())),
+                    duration: (!delay.is_zero()).then_some(delay),
+                };
+                if sender.send(frame).is_err() {
+                    return;
+                }
+                count += 1;
+            }
+            Err(_) if count == 0 => return,
+            Err(err) => {
+                // The next frames are drawn over this one, they cannot be decoded.
+                warn!(
+                    "Stopped reading {} at frame {}: {}",
+                    path.display(),
+                    count + 1,
+                    err
+                );
+                return;
+            }
+        }
+    }
+    debug!("Read {} frames from {}", count, path.display());
+}
+
+/// Returns the lazy frames of an animated image file, or `None` if the file is
+/// not an animation or cannot be read.
+fn animation_decoder(path: &Path) -> Option<image::Frames<'static>> {
+    let format = detect_format(path).ok()??;
+    let reader = BufReader::new(File::open(path).ok()?);
+    Some(match format {
+        ImageFormat::Gif => GifDecoder::new(reader).ok()?.into_frames(),
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(reader).ok()?;
+            if !decoder.is_apng() {
+                return None;
+            }
+            decoder.apng().into_frames()
+        }
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(reader).ok()?;
+            if !decoder.has_animation() {
+                return None;
+            }
+            decoder.into_frames()
+        }
+        _ => return None,
+    })
+}
+
+impl Iterator for FrameSource {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.frames.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{jpeg_with_exif, test_dir};
+
+    fn names(dir: &Path, settings: &InputSettings) -> Vec<String> {
+        get_image_paths(&dir, settings)
+            .unwrap()
+            .iter()
+            .map(|path| path.strip_prefix(dir).unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn directory_traversal() {
+        let dir = test_dir("traversal");
+        for path in [
+            "cover.png",
+            "DCIM/100CANON/IMG_0001.JPG",
+            "DCIM/100CANON/IMG_0002.jpg",
+            "DCIM/100CANON/thumbs/IMG_0001.JPG",
+            "MISC/IMG_0003.JPG",
+        ] {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            DynamicImage::new_rgb8(1, 1)
+                .save_with_format(path, ImageFormat::Png)
+                .unwrap();
+        }
+        fs::write(dir.join("notes.txt"), b"not an image").unwrap();
+
+        assert_eq!(names(&dir, &InputSettings::default()), vec!["cover.png"]);
+
+        let recursive = InputSettings {
+            recursive: true,
+            ..InputSettings::default()
+        };
+        assert_eq!(names(&dir, &recursive).len(), 5);
+
+        let limited = InputSettings {
+            max_depth: Some(2),
+            exclude: vec!["MISC".to_string()],
+            include: vec!["IMG_*".to_string()],
+            ..InputSettings::default()
+        };
+        assert_eq!(
+            names(&dir, &limited),
+            vec!["DCIM/100CANON/IMG_0001.JPG", "DCIM/100CANON/IMG_0002.jpg"]
+        );
+
+        let paths = InputSettings {
+            recursive: true,
+            include: vec!["DCIM/*/*.JPG".to_string()],
+            ..InputSettings::default()
+        };
+        assert_eq!(names(&dir, &paths), vec!["DCIM/100CANON/IMG_0001.JPG"]);
+
+        let invalid = InputSettings {
+            include: vec!["[".to_string()],
+            ..InputSettings::default()
+        };
+        assert!(matches!(
+            get_image_paths(&dir, &invalid),
+            Err(Error::Input(_))
+        ));
+        let invalid = InputSettings {
+            formats: vec!["doc".to_string()],
+            ..InputSettings::default()
+        };
+        assert!(matches!(
+            get_image_paths(&dir, &invalid),
+            Err(Error::Input(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn content_sniffing() {
+        let dir = test_dir("sniffing");
+        let image = DynamicImage::new_rgb8(2, 2);
+        image
+            .save_with_format(dir.join("frame_1.dat"), ImageFormat::Png)
+            .unwrap();
+        image
+            .save_with_format(dir.join("frame_2.jpg"), ImageFormat::Bmp)
+            .unwrap();
+        image
+            .save_with_format(dir.join("frame_3.QOI"), ImageFormat::Qoi)
+            .unwrap();
+        fs::write(dir.join("frame_4.png"), b"not an image").unwrap();
+
+        assert_eq!(
+            names(&dir, &InputSettings::default()),
+            vec!["frame_1.dat", "frame_2.jpg", "frame_3.QOI"]
+        );
+        let allowed = InputSettings {
+            formats: vec!["png".to_string(), "bmp".to_string()],
+            ..InputSettings::default()
+        };
+        assert_eq!(names(&dir, &allowed), vec!["frame_1.dat", "frame_2.jpg"]);
+        assert_eq!(
+            FrameInput::Path(dir.join("frame_2.jpg"))
+                .dimensions(true)
+                .unwrap(),
+            (2, 2)
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_files() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = test_dir("unreadable");
+        let image = DynamicImage::new_rgb8(2, 2);
+        for name in ["frame_1.png", "frame_2.png"] {
+            image
+                .save_with_format(dir.join(name), ImageFormat::Png)
+                .unwrap();
+        }
+        let locked = dir.join("frame_2.png");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        // A link to a missing file cannot be opened by anyone.
+        std::os::unix::fs::symlink(dir.join("missing.png"), dir.join("frame_3.png")).unwrap();
+
+        // Permissions do not apply to the superuser, who can still read the file.
+        let expected = match File::open(&locked) {
+            Ok(_) => vec!["frame_1.png", "frame_2.png"],
+            Err(_) => vec!["frame_1.png"],
+        };
+        assert_eq!(names(&dir, &InputSettings::default()), expected);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = test_dir("unreadable-directories");
+        for path in ["DCIM/IMG_0001.png", "lost+found/IMG_0002.png"] {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            DynamicImage::new_rgb8(1, 1)
+                .save_with_format(path, ImageFormat::Png)
+                .unwrap();
+        }
+        let locked = dir.join("lost+found");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+        let recursive = InputSettings {
+            recursive: true,
+            ..InputSettings::default()
+        };
+        // Permissions do not apply to the superuser, who can still read the directory.
+        let expected = match fs::read_dir(&locked) {
+            Ok(_) => vec!["DCIM/IMG_0001.png", "lost+found/IMG_0002.png"],
+            Err(_) => vec!["DCIM/IMG_0001.png"],
+        };
+        assert_eq!(names(&dir, &recursive), expected);
+        // The input directory itself must be readable.
+        assert!(get_image_paths(&dir.join("missing"), &recursive).is_err());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn decode_error() {
+        let dir = test_dir("decode-error");
+        let path = dir.join("truncated.png");
+        let mut data = Vec::new();
+        DynamicImage::new_rgb8(16, 16)
+            .write_to(&mut io::Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        fs::write(&path, &data[..data.len() / 2]).unwrap();
+        match FrameInput::Path(path.clone()).load(true) {
+            Err(Error::Decode { path: failed, .. }) => assert_eq!(failed, path),
+            _ => panic!("expected a decode error"),
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn animations() {
+        let dir = test_dir("animations");
+        let gif = dir.join("animation.gif");
+        let mut encoder = image::codecs::gif::GifEncoder::new(File::create(&gif).unwrap());
+        for (millis, color) in [(100, 0), (250, 255)] {
+            let buffer = image::RgbaImage::from_pixel(4, 4, image::Rgba([color, 0, 0, 255]));
+            let delay = image::Delay::from_numer_denom_ms(millis, 1);
+            encoder
+                .encode_frame(image::Frame::from_parts(buffer, 0, 0, delay))
+                .unwrap();
+        }
+        drop(encoder);
+        let still = dir.join("still.png");
+        DynamicImage::new_rgb8(4, 4).save(&still).unwrap();
+        let still_gif = dir.join("still.gif");
+        DynamicImage::new_rgb8(4, 4).save(&still_gif).unwrap();
+        assert_eq!(animation_frame_count(&still), None);
+        assert_eq!(animation_frame_count(&still_gif), None);
+
+        let source = FrameSource::from_paths(vec![still.clone(), gif.clone(), still]);
+        assert_eq!(source.frame_count(), Some(4));
+        assert_eq!(source.replay().unwrap().frame_count(), Some(4));
+        let frames: Vec<Frame> = source.collect();
+        let durations: Vec<Option<Duration>> = frames.iter().map(|frame| frame.duration).collect();
+        assert_eq!(
+            durations,
+            vec![
+                None,
+                Some(Duration::from_millis(100)),
+                Some(Duration::from_millis(250)),
+                None
+            ]
+        );
+        assert!(matches!(frames[1].input, FrameInput::Image(_)));
+
+        // The duration given to an animation is ignored, as its frames keep their delays.
+        let frame = Frame {
+            input: FrameInput::Path(gif),
+            duration: Some(Duration::from_secs(5)),
+        };
+        let frames: Vec<Option<Duration>> = FrameSource::from_frames(vec![frame])
+            .map(|frame| frame.duration)
+            .collect();
+        assert_eq!(frames, durations[1..3]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn animation_headers() {
+        let dir = test_dir("animation-headers");
+        let chunk = |kind: &[u8], data: &[u8]| {
+            let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+            chunk.extend(kind);
+            chunk.extend(data);
+            chunk.extend([0; 4]);
+            chunk
+        };
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend(chunk(b"IHDR", &[0; 13]));
+        let mut apng = png.clone();
+        apng.extend(chunk(b"acTL", &[0, 0, 0, 3, 0, 0, 0, 0]));
+        png.extend(chunk(b"IDAT", &[0; 4]));
+
+        let riff = |chunks: &[(&[u8], &[u8])]| {
+            let mut data = b"WEBP".to_vec();
+            for (kind, payload) in chunks {
+                data.extend(*kind);
+                data.extend((payload.len() as u32).to_le_bytes());
+                data.extend(*payload);
+                if payload.len() % 2 == 1 {
+                    data.push(0);
+                }
+            }
+            let mut webp = b"RIFF".to_vec();
+            webp.extend((data.len() as u32).to_le_bytes());
+            webp.extend(data);
+            webp
+        };
+        let animated = [0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let webp = riff(&[
+            (b"VP8X", &animated),
+            (b"ANIM", &[0; 6]),
+            (b"ANMF", &[0; 17]),
+        ]);
+        let animated_webp = riff(&[
+            (b"VP8X", &animated),
+            (b"ANIM", &[0; 6]),
+            (b"ANMF", &[0; 17]),
+            (b"ANMF", &[0; 21]),
+        ]);
+        let still_webp = riff(&[(b"VP8X", &[0; 10]), (b"VP8L", &[0; 5])]);
+        // A chunk claiming the largest size, padded beyond the range of `u32`.
+        let mut crafted_webp = animated_webp.clone();
+        crafted_webp.extend(b"ANMF");
+        crafted_webp.extend(u32::MAX.to_le_bytes());
+
+        let counts: Vec<Option<usize>> = [
+            &png,
+            &apng,
+            &webp,
+            &animated_webp,
+            &still_webp,
+            &crafted_webp,
+            &b"not an image".to_vec(),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(index, data)| {
+            let path = dir.join(index.to_string());
+            fs::write(&path, data).unwrap();
+            animation_frame_count(&path)
+        })
+        .collect();
+        assert_eq!(
+            counts,
+            vec![None, Some(3), None, Some(2), None, Some(3), None]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn truncated_animations() {
+        let dir = test_dir("truncated-animation");
+        let gif = dir.join("animation.gif");
+        let mut encoder = image::codecs::gif::GifEncoder::new(File::create(&gif).unwrap());
+        for frame in 0..3u32 {
+            let buffer = image::RgbaImage::from_fn(16, 16, |x, y| {
+                image::Rgba([(x * 16) as u8, (y * 16) as u8, (frame * 80) as u8, 255])
+            });
+            encoder.encode_frame(image::Frame::new(buffer)).unwrap();
+        }
+        drop(encoder);
+        assert_eq!(animation_frame_count(&gif), Some(3));
+        let data = fs::read(&gif).unwrap();
+        fs::write(&gif, &data[..data.len() - 40]).unwrap();
+        assert_eq!(animation_frame_count(&gif), Some(2));
+
+        // The frames decoded before the error are kept.
+        let frames = FrameSource::from_paths(vec![gif]).count();
+        assert_eq!(frames, 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn replay() {
+        let paths: Vec<PathBuf> = ["a.png", "b.png"].iter().map(PathBuf::from).collect();
+        let mut source = FrameSource::from_paths(paths.clone());
+        source.origin = "frames".to_string();
+        assert_eq!(source.by_ref().count(), 2);
+        let replayed = source.replay().unwrap();
+        assert_eq!(replayed.origin(), "frames");
+        let replayed: Vec<PathBuf> = replayed
+            .map(|frame| match frame.input {
+                FrameInput::Path(path) => path,
+                FrameInput::Image(_) => panic!("expected a path"),
+            })
+            .collect();
+        assert_eq!(replayed, paths);
+
+        let images = FrameSource::from_images(vec![DynamicImage::new_rgb8(2, 2)]);
+        assert!(images.replay().is_none());
+    }
+
+    /// Returns a JPEG file with an EXIF orientation.
+    fn jpeg_with_orientation(image: &DynamicImage, orientation: u16) -> Vec<u8> {
+        let field = exif::Field {
+            tag: exif::Tag::Orientation,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Short(vec![orientation]),
+        };
+        jpeg_with_exif(image, &[field])
+    }
+
+    #[test]
+    fn exif_orientation() {
+        let dir = test_dir("orientation");
+        // A white column on the left of a black 16x8 image.
+        let mut image = image::RgbImage::new(16, 8);
+        for y in 0..8 {
+            image.put_pixel(0, y, image::Rgb([255, 255, 255]));
+        }
+        let image = DynamicImage::ImageRgb8(image);
+        let path = dir.join("portrait.jpg");
+        fs::write(&path, jpeg_with_orientation(&image, 6)).unwrap();
+
+        let input = FrameInput::Path(path.clone());
+        assert_eq!(input.dimensions(true).unwrap(), (8, 16));
+        assert_eq!(input.dimensions(false).unwrap(), (16, 8));
+        // Rotated clockwise, the left column becomes the top row.
+        let rotated = input.load(true).unwrap().into_luma8();
+        assert_eq!(rotated.dimensions(), (8, 16));
+        assert!(rotated.get_pixel(4, 0)[0] > 192);
+        assert!(rotated.get_pixel(4, 15)[0] < 64);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn orientations() {
+        // 2x1 image, black then white.
+        let mut image = image::GrayImage::new(2, 1);
+        image.put_pixel(1, 0, image::Luma([255]));
+        let image = DynamicImage::ImageLuma8(image);
+        let pixels = |orientation| {
+            Orientation(orientation)
+                .apply(image.clone())
+                .into_luma8()
+                .into_raw()
+        };
+        assert_eq!(pixels(1), vec![0, 255]);
+        assert_eq!(pixels(2), vec![255, 0]);
+        assert_eq!(pixels(3), vec![255, 0]);
+        assert_eq!(pixels(4), vec![0, 255]);
+        assert_eq!(pixels(5), vec![0, 255]);
+        assert_eq!(pixels(6), vec![0, 255]);
+        assert_eq!(pixels(7), vec![255, 0]);
+        assert_eq!(pixels(8), vec![255, 0]);
+    }
 }
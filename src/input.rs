@@ -0,0 +1,352 @@
+//! Contains the resolution of input arguments into image paths.
+//!
+//! An input is one of:
+//! * a directory, whose images are listed and sorted,
+//! * an image file,
+//! * a glob pattern (`shots/*_beauty.png`), whose matches are sorted,
+//! * a printf-style sequence (`frame_%05d.png`), read in numeric order until
+//!   the first missing file,
+//! * a file list, with one path per line, read from a file or the standard
+//!   input (`-`).
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::images::{get_image_paths, FormatFilter};
+use crate::settings::InputSettings;
+use crate::sort::sort_paths;
+use crate::Result;
+
+/// Number of indices probed for the first image of a sequence when no start
+/// number is given, like ffmpeg does.
+const SEQUENCE_START_RANGE: u64 = 5;
+
+/// A source of images given on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// All images of a directory.
+    Directory(PathBuf),
+    /// A single image file.
+    File(PathBuf),
+    /// Files matching a glob pattern.
+    Glob(String),
+    /// Numbered files following a printf-style pattern.
+    Sequence(String),
+    /// Files listed in a file, or the standard input if it is `-`.
+    List(String),
+}
+
+impl Input {
+    /// Guess the kind of input from an argument.
+    /// Existing paths are taken literally, so a file named `a[1].png` is not a glob.
+    pub fn parse(arg: &str) -> Self {
+        let path = Path::new(arg);
+        if path.is_dir() {
+            Input::Directory(path.to_path_buf())
+        } else if path.exists() {
+            Input::File(path.to_path_buf())
+        } else if is_sequence(arg) {
+            Input::Sequence(arg.to_string())
+        } else if arg.contains(['*', '?', '[']) {
+            Input::Glob(arg.to_string())
+        } else {
+            Input::File(path.to_path_buf())
+        }
+    }
+
+    /// Returns the paths of the images of this input, in presentation order.
+    ///
+    /// Directories and glob patterns skip files which are not images in an allowed
+    /// format, while explicitly named files must be in one.
+    ///
+    /// # Errors
+    /// If a directory or a file list cannot be read, a pattern is invalid or
+    /// matches no image, a file does not exist or is not in an allowed format, an
+    /// error is returned.
+    pub fn paths(&self, settings: &InputSettings) -> Result<Vec<PathBuf>> {
+        let formats = FormatFilter::new(&settings.formats)?;
+        let paths = self.find_paths(settings, &formats)?;
+        // A mistyped pattern would otherwise drop its images from the video unnoticed.
+        if paths.is_empty() && matches!(self, Input::Glob(_) | Input::Sequence(_)) {
+            return Err(Error::Input(format!("{}: no matching files", self)));
+        }
+        if !matches!(self, Input::Directory(_) | Input::Glob(_)) && !settings.formats.is_empty() {
+            for path in &paths {
+                if !formats.accepts(path)? {
+                    return Err(Error::Input(format!(
+                        "{}: not an image in an allowed format",
+                        path.display()
+                    )));
+                }
+            }
+        }
+        Ok(paths)
+    }
+
+    fn find_paths(&self, settings: &InputSettings, formats: &FormatFilter) -> Result<Vec<PathBuf>> {
+        match self {
+            Input::Directory(path) => get_image_paths(path, &unreversed(settings)),
+            Input::File(path) => match path.is_file() {
+                true => Ok(vec![path.clone()]),
+                false => Err(Error::Input(format!("{}: no such file", path.display()))),
+            },
+            Input::Glob(pattern) => {
+                let matches = glob::glob(pattern)
+                    .map_err(|err| Error::Input(format!("{}: {}", pattern, err)))?;
+                let mut paths = Vec::new();
+                for path in matches {
+                    let path = path.map_err(|err| Error::IO(err.into()))?;
+                    if path.is_file() && formats.keeps(&path) {
+                        paths.push(path);
+                    }
+                }
+                sort_paths(&mut paths, settings.sort, false);
+                Ok(paths)
+            }
+            Input::Sequence(pattern) => Ok(sequence_paths(pattern, settings.start_number)),
+            Input::List(list) if list == "-" => read_list(io::stdin().lock(), Path::new("")),
+            Input::List(list) => {
+                let file = fs::File::open(list)?;
+                let base = Path::new(list).parent().unwrap_or_else(|| Path::new(""));
+                read_list(io::BufReader::new(file), base)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Input::Directory(path) | Input::File(path) => write!(f, "{}", path.display()),
+            Input::Glob(pattern) | Input::Sequence(pattern) => write!(f, "{}", pattern),
+            Input::List(list) if list == "-" => write!(f, "standard input"),
+            Input::List(list) => write!(f, "{}", list),
+        }
+    }
+}
+
+/// Returns the paths of the images of all inputs, one input after the other.
+///
+/// # Errors
+/// If an input cannot be resolved, an error is returned.
+pub fn resolve_inputs(inputs: &[Input], settings: &InputSettings) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for input in inputs {
+        let input_paths = input.paths(settings)?;
+        debug!("{} images from {}", input_paths.len(), input);
+        paths.extend(input_paths);
+    }
+    if settings.reverse {
+        paths.reverse();
+    }
+    Ok(paths)
+}
+
+/// The settings without the reverse flag, which applies to all inputs at once.
+fn unreversed(settings: &InputSettings) -> InputSettings {
+    InputSettings {
+        reverse: false,
+        ..settings.clone()
+    }
+}
+
+/// Returns whether a pattern contains a printf-style integer conversion.
+fn is_sequence(pattern: &str) -> bool {
+    format_sequence(pattern, 0).is_some()
+}
+
+/// Replace the integer conversion of a printf-style pattern with `index`.
+/// Only `%d` with an optional zero-padded width (`%05d`) is supported, and `%%`
+/// is a literal `%`.
+///
+/// Returns `None` if the pattern does not contain exactly one conversion.
+fn format_sequence(pattern: &str, index: u64) -> Option<String> {
+    let mut result = String::with_capacity(pattern.len());
+    let mut conversions = 0;
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        if chars.next_if_eq(&'%').is_some() {
+            result.push('%');
+            continue;
+        }
+        let mut width = String::new();
+        while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+            width.push(digit);
+        }
+        chars.next_if_eq(&'d')?;
+        let width: usize = match width.is_empty() {
+            true => 0,
+            false => width.parse().ok()?,
+        };
+        result.push_str(&format!("{:0width$}", index, width = width));
+        conversions += 1;
+    }
+    match conversions {
+        1 => Some(result),
+        _ => None,
+    }
+}
+
+/// Returns the consecutive files of a sequence, stopping at the first missing one.
+/// Without a start number, the first file is searched among the first indices.
+fn sequence_paths(pattern: &str, start_number: Option<u64>) -> Vec<PathBuf> {
+    let path = |index| PathBuf::from(format_sequence(pattern, index).unwrap_or_default());
+    let start = match start_number {
+        Some(start) => start,
+        None => match (0..SEQUENCE_START_RANGE).find(|&index| path(index).is_file()) {
+            Some(start) => start,
+            None => return Vec::new(),
+        },
+    };
+    (start..)
+        .map(path)
+        .take_while(|path| path.is_file())
+        .collect()
+}
+
+/// Read a file list, skipping empty lines and `#` comments.
+/// Relative paths are resolved from `base`.
+fn read_list<R: BufRead>(reader: R, base: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        paths.push(base.join(line));
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_dir;
+
+    #[test]
+    fn sequence_patterns() {
+        assert_eq!(
+            format_sequence("frame_%05d.exr", 42).as_deref(),
+            Some("frame_00042.exr")
+        );
+        assert_eq!(format_sequence("%d.png", 7).as_deref(), Some("7.png"));
+        assert_eq!(
+            format_sequence("100%%_%03d.png", 1).as_deref(),
+            Some("100%_001.png")
+        );
+        assert_eq!(format_sequence("frame.png", 1), None);
+        assert_eq!(format_sequence("%d_%d.png", 1), None);
+        assert_eq!(format_sequence("%s.png", 1), None);
+    }
+
+    #[test]
+    fn parse_inputs() {
+        assert_eq!(Input::parse("src"), Input::Directory("src".into()));
+        assert_eq!(
+            Input::parse("shots/*_beauty.png"),
+            Input::Glob("shots/*_beauty.png".into())
+        );
+        assert_eq!(
+            Input::parse("frame_%05d.png"),
+            Input::Sequence("frame_%05d.png".into())
+        );
+        assert_eq!(Input::parse("a.png"), Input::File("a.png".into()));
+    }
+
+    #[test]
+    fn resolve_sequence_and_list() {
+        let dir = test_dir("inputs");
+        for index in [1, 2, 3, 5] {
+            image::DynamicImage::new_rgb8(1, 1)
+                .save(dir.join(format!("frame_{:03}.png", index)))
+                .unwrap();
+        }
+        fs::write(
+            dir.join("list.txt"),
+            "# frames\nframe_005.png\n\nframe_001.png\n",
+        )
+        .unwrap();
+        let pattern = dir.join("frame_%03d.png").display().to_string();
+        let settings = InputSettings::default();
+
+        let names = |inputs: &[Input], settings: &InputSettings| -> Vec<String> {
+            resolve_inputs(inputs, settings)
+                .unwrap()
+                .iter()
+                .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+        let inputs = [
+            Input::Sequence(pattern.clone()),
+            Input::List(dir.join("list.txt").display().to_string()),
+        ];
+        assert_eq!(
+            names(&inputs, &settings),
+            vec![
+                "frame_001.png",
+                "frame_002.png",
+                "frame_003.png",
+                "frame_005.png",
+                "frame_001.png"
+            ]
+        );
+
+        let settings = InputSettings {
+            start_number: Some(2),
+            reverse: true,
+            ..InputSettings::default()
+        };
+        assert_eq!(
+            names(&[Input::Sequence(pattern)], &settings),
+            vec!["frame_003.png", "frame_002.png"]
+        );
+
+        let glob = dir.join("frame_00[125].png").display().to_string();
+        assert_eq!(
+            names(&[Input::Glob(glob)], &InputSettings::default()),
+            vec!["frame_001.png", "frame_002.png", "frame_005.png"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unmatched_patterns() {
+        let dir = test_dir("unmatched");
+        image::DynamicImage::new_rgb8(1, 1)
+            .save(dir.join("frame_009.png"))
+            .unwrap();
+        let settings = InputSettings::default();
+        let pattern = |name: &str| dir.join(name).display().to_string();
+        let missing_start = InputSettings {
+            start_number: Some(4),
+            ..InputSettings::default()
+        };
+        for (input, settings) in [
+            // The first file is past the probed start range.
+            (Input::Sequence(pattern("frame_%03d.png")), &settings),
+            (Input::Sequence(pattern("frame_%03d.png")), &missing_start),
+            (Input::Sequence(pattern("shot_%03d.png")), &settings),
+            (Input::Glob(pattern("shot_*.png")), &settings),
+        ] {
+            match input.paths(settings) {
+                Err(Error::Input(message)) => assert!(message.contains("no matching files")),
+                result => panic!("{} resolved to {:?}", input, result),
+            }
+        }
+        // One unmatched input fails the whole resolution.
+        let inputs = [
+            Input::Glob(pattern("frame_*.png")),
+            Input::Glob(pattern("frmae_*.png")),
+        ];
+        assert!(resolve_inputs(&inputs, &settings).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,28 @@
+//! Convert a sequence of images to a video.
+//!
+//! The [VideoBuilder] encodes a [FrameSource](images::FrameSource) into a WebM
+//! video using the given [VideoSettings](settings::VideoSettings).
+
+pub mod convert;
+pub mod encoder;
+pub mod error;
+pub mod images;
+pub mod input;
+pub mod manifest;
+pub mod mux;
+pub mod output;
+mod pipeline;
+pub mod settings;
+pub mod sort;
+#[cfg(test)]
+mod test_utils;
+mod video;
+
+pub use error::Error;
+pub use video::VideoBuilder;
+
+#[macro_use]
+extern crate log;
+
+/// Result type of the library.
+pub type Result<T> = std::result::Result<T, Error>;
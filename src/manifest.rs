@@ -0,0 +1,345 @@
+//! Contains the parsing of timing manifests.
+//!
+//! A manifest lists the images of a video with how long each one is shown.
+//! It is either a TOML or JSON file:
+//!
+//! ```toml
+//! [[frames]]
+//! path = "title.png"
+//! duration = 2.5
+//!
+//! [[frames]]
+//! path = "slide.png"
+//! duration = 0.5
+//! repeat = 3
+//! ```
+//!
+//! or a plain text file in the style of ffmpeg's concat demuxer:
+//!
+//! ```text
+//! file 'title.png'
+//! duration 2.5
+//! file 'slide.png'
+//! duration 0.5
+//! repeat 3
+//! ```
+//!
+//! Entries are shown in the order they are listed, unless they have an `order`
+//! key, in which case they are sorted by it (entries without one count as 0).
+//! Relative paths are resolved from the directory of the manifest.
+//! Animated images are expanded into their frames, which keep their own delays,
+//! so they cannot be given a duration.
+
+use serde_derive::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::images::{animation_frame_count, Frame, FrameInput};
+use crate::Result;
+
+/// A timing manifest.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Manifest {
+    /// The entries of the manifest.
+    pub frames: Vec<ManifestEntry>,
+}
+
+/// An image listed in a timing manifest.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ManifestEntry {
+    /// Path of the image.
+    pub path: PathBuf,
+
+    /// How long the image is shown, in seconds.
+    /// If not specified, the duration given by the frame rate is used.
+    pub duration: Option<f64>,
+
+    /// How many times the image is shown in a row.
+    pub repeat: Option<u32>,
+
+    /// Position of the image in the video.
+    pub order: Option<i64>,
+}
+
+impl ManifestEntry {
+    fn new(path: PathBuf) -> Self {
+        ManifestEntry {
+            path,
+            duration: None,
+            repeat: None,
+            order: None,
+        }
+    }
+}
+
+impl Manifest {
+    /// Read a manifest file.
+    /// The format is chosen from the extension: `.toml`, `.json`, or plain text otherwise.
+    ///
+    /// # Errors
+    /// If the file cannot be read or parsed, an error is returned.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        let mut manifest = match extension.as_deref() {
+            Some("toml") => toml::from_str(&content).map_err(|err| manifest_error(path, err))?,
+            Some("json") => {
+                serde_json::from_str(&content).map_err(|err| manifest_error(path, err))?
+            }
+            _ => Self::parse_text(&content).map_err(|err| manifest_error(path, err))?,
+        };
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        for entry in manifest.frames.iter_mut() {
+            if entry.path.is_relative() {
+                entry.path = base.join(&entry.path);
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Parse a plain text manifest.
+    ///
+    /// # Errors
+    /// If a line is not a valid directive, an error describing it is returned.
+    pub fn parse_text(content: &str) -> std::result::Result<Self, String> {
+        let mut manifest = Manifest::default();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (directive, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            let invalid = || format!("line {}: invalid value `{}`", number + 1, value);
+            if directive == "ffconcat" {
+                continue;
+            }
+            if directive == "file" {
+                let path = unquote(value).ok_or_else(invalid)?;
+                manifest.frames.push(ManifestEntry::new(path.into()));
+                continue;
+            }
+            let entry = manifest
+                .frames
+                .last_mut()
+                .ok_or_else(|| format!("line {}: `{}` before any file", number + 1, directive))?;
+            match directive {
+                "duration" => entry.duration = Some(value.parse().map_err(|_| invalid())?),
+                "repeat" => entry.repeat = Some(value.parse().map_err(|_| invalid())?),
+                "order" => entry.order = Some(value.parse().map_err(|_| invalid())?),
+                _ => {
+                    return Err(format!(
+                        "line {}: unknown directive `{}`",
+                        number + 1,
+                        directive
+                    ))
+                }
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Returns the frames of the manifest, in presentation order, with how many
+    /// times each one is shown in a row. Repeated frames are listed once, so
+    /// that they are only expanded as they are read.
+    ///
+    /// # Errors
+    /// If a duration is not a positive number or is given to an animation, or if
+    /// an image is repeated less than once, an error naming the entry is returned.
+    pub fn into_frames(mut self) -> Result<Vec<(Frame, u32)>> {
+        self.frames.sort_by_key(|entry| entry.order.unwrap_or(0));
+        let mut frames = Vec::new();
+        for entry in self.frames {
+            let duration = match entry.duration {
+                Some(seconds) => Some(
+                    Duration::try_from_secs_f64(seconds)
+                        .ok()
+                        .filter(|duration| !duration.is_zero())
+                        .ok_or_else(|| {
+                            Error::Manifest(format!(
+                                "invalid duration {} for {}",
+                                seconds,
+                                entry.path.display()
+                            ))
+                        })?,
+                ),
+                None => None,
+            };
+            if duration.is_some() && animation_frame_count(&entry.path).is_some() {
+                return Err(Error::Manifest(format!(
+                    "{} is an animation, its frames keep their own delays instead of a duration",
+                    entry.path.display()
+                )));
+            }
+            if entry.repeat == Some(0) {
+                return Err(Error::Manifest(format!(
+                    "invalid repeat 0 for {}",
+                    entry.path.display()
+                )));
+            }
+            let frame = Frame {
+                input: FrameInput::Path(entry.path),
+                duration,
+            };
+            frames.push((frame, entry.repeat.unwrap_or(1)));
+        }
+        Ok(frames)
+    }
+}
+
+/// Remove the quotes around a path, if any.
+fn unquote(value: &str) -> Option<&str> {
+    for quote in ['\'', '"'] {
+        if let Some(inner) = value.strip_prefix(quote) {
+            return inner.strip_suffix(quote);
+        }
+    }
+    match value.is_empty() {
+        true => None,
+        false => Some(value),
+    }
+}
+
+fn manifest_error<E: std::fmt::Display>(path: &Path, err: E) -> Error {
+    Error::Manifest(format!("{}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::images::FrameSource;
+    use crate::test_utils::test_dir;
+    use std::iter;
+
+    fn durations(manifest: Manifest) -> Vec<(PathBuf, Option<Duration>)> {
+        manifest
+            .into_frames()
+            .unwrap()
+            .into_iter()
+            .flat_map(|(frame, repeat)| iter::repeat_n(frame, repeat as usize))
+            .map(|frame| match frame.input {
+                FrameInput::Path(path) => (path, frame.duration),
+                FrameInput::Image(_) => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn text_manifest() {
+        let manifest = Manifest::parse_text(
+            "ffconcat version 1.0\n# title\nfile 'a.png'\nduration 2.5\n\nfile b.png\nrepeat 2\n",
+        )
+        .unwrap();
+        assert_eq!(
+            durations(manifest),
+            vec![
+                ("a.png".into(), Some(Duration::from_millis(2500))),
+                ("b.png".into(), None),
+                ("b.png".into(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn text_manifest_errors() {
+        assert!(Manifest::parse_text("duration 1").is_err());
+        assert!(Manifest::parse_text("file a.png\nduration fast").is_err());
+        assert!(Manifest::parse_text("file a.png\nspeed 2").is_err());
+        assert!(Manifest::parse_text("file 'a.png").is_err());
+    }
+
+    #[test]
+    fn ordered_manifest() {
+        let manifest: Manifest = toml::from_str(
+            "[[frames]]\npath = \"b.png\"\norder = 2\n\n[[frames]]\npath = \"a.png\"\norder = 1\nduration = 1\n",
+        )
+        .unwrap();
+        assert_eq!(
+            durations(manifest),
+            vec![
+                ("a.png".into(), Some(Duration::from_secs(1))),
+                ("b.png".into(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn json_manifest() {
+        let manifest: Manifest = serde_json::from_str(
+            r#"{"frames": [{"path": "a.png", "duration": 0.04, "repeat": 3}]}"#,
+        )
+        .unwrap();
+        assert_eq!(durations(manifest).len(), 3);
+    }
+
+    #[test]
+    fn lazy_repeat() {
+        let dir = test_dir("manifest-repeat");
+        let path = dir.join("manifest.txt");
+        fs::write(&path, "file a.png\nrepeat 4000000000\nfile b.png\n").unwrap();
+        let mut source = FrameSource::from_manifest(&path).unwrap();
+        assert_eq!(source.frame_count(), Some(4_000_000_001));
+        assert_eq!(source.by_ref().take(3).count(), 3);
+        assert_eq!(source.replay().unwrap().frame_count(), Some(4_000_000_001));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn negative_duration() {
+        let manifest = Manifest::parse_text("file a.png\nduration -1").unwrap();
+        assert!(matches!(manifest.into_frames(), Err(Error::Manifest(_))));
+    }
+
+    #[test]
+    fn zero_duration() {
+        let manifest = Manifest::parse_text("file a.png\nfile b.png\nduration 0").unwrap();
+        match manifest.into_frames() {
+            Err(Error::Manifest(message)) => assert!(message.contains("b.png")),
+            _ => panic!("a zero duration must be rejected"),
+        }
+    }
+
+    #[test]
+    fn animation_duration() {
+        let dir = test_dir("manifest-animation");
+        let gif = dir.join("animation.gif");
+        let mut encoder = image::codecs::gif::GifEncoder::new(fs::File::create(&gif).unwrap());
+        for _ in 0..2 {
+            let frame = image::Frame::new(image::RgbaImage::new(2, 2));
+            encoder.encode_frame(frame).unwrap();
+        }
+        drop(encoder);
+
+        let mut entry = ManifestEntry::new(gif);
+        assert_eq!(
+            durations(Manifest {
+                frames: vec![entry.clone()]
+            })
+            .len(),
+            1
+        );
+        entry.duration = Some(5.0);
+        match (Manifest {
+            frames: vec![entry],
+        })
+        .into_frames()
+        {
+            Err(Error::Manifest(message)) => assert!(message.contains("animation.gif")),
+            _ => panic!("a duration must not be given to an animation"),
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn zero_repeat() {
+        let manifest: Manifest =
+            toml::from_str("[[frames]]\npath = \"a.png\"\nrepeat = 0\n").unwrap();
+        match manifest.into_frames() {
+            Err(Error::Manifest(message)) => assert!(message.contains("a.png")),
+            _ => panic!("a zero repeat must be rejected"),
+        }
+    }
+}
@@ -0,0 +1,825 @@
+//! Minimal WebM multiplexer for a single video track.
+//!
+//! Clusters are buffered in memory until they are complete, so their sizes are
+//! always known when they are written. When the output can seek, the segment
+//! size, duration, seek head and cues are filled in when the file is finalized.
+//! Otherwise a live WebM is written: the segment has an unknown size and no
+//! index, which players accept as a stream.
+
+use std::io::{self, Write};
+
+/// Function used to move the write position of a seekable output.
+pub type SeekFn<W> = fn(&mut W, u64) -> io::Result<()>;
+
+/// Timecode scale of the segment, in nanoseconds per tick.
+/// A tenth of a millisecond keeps fractional frame rates such as 30000/1001
+/// within 50 µs of their exact timestamps.
+const TIMECODE_SCALE: u64 = 100_000;
+
+/// Name written in the segment information.
+const APP_NAME: &str = concat!("img-to-video ", env!("CARGO_PKG_VERSION"));
+
+/// Track number of the video track.
+const TRACK_NUMBER: u64 = 1;
+
+/// Value of an EBML size field meaning "unknown size".
+const UNKNOWN_SIZE: u64 = 0x00FF_FFFF_FFFF_FFFF;
+
+const EBML: u32 = 0x1A45_DFA3;
+const EBML_VERSION: u32 = 0x4286;
+const EBML_READ_VERSION: u32 = 0x42F7;
+const EBML_MAX_ID_LENGTH: u32 = 0x42F2;
+const EBML_MAX_SIZE_LENGTH: u32 = 0x42F3;
+const DOC_TYPE: u32 = 0x4282;
+const DOC_TYPE_VERSION: u32 = 0x4287;
+const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+const SEGMENT: u32 = 0x1853_8067;
+const SEEK_HEAD: u32 = 0x114D_9B74;
+const SEEK: u32 = 0x4DBB;
+const SEEK_ID: u32 = 0x53AB;
+const SEEK_POSITION: u32 = 0x53AC;
+const INFO: u32 = 0x1549_A966;
+const TIMECODE_SCALE_ID: u32 = 0x2A_D7B1;
+const DURATION: u32 = 0x4489;
+const MUXING_APP: u32 = 0x4D80;
+const WRITING_APP: u32 = 0x5741;
+const TRACKS: u32 = 0x1654_AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER_ID: u32 = 0xD7;
+const TRACK_UID: u32 = 0x73C5;
+const TRACK_TYPE: u32 = 0x83;
+const FLAG_LACING: u32 = 0x9C;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const MAX_BLOCK_ADDITION_ID: u32 = 0x55EE;
+const VIDEO: u32 = 0xE0;
+const PIXEL_WIDTH: u32 = 0xB0;
+const PIXEL_HEIGHT: u32 = 0xBA;
+const ALPHA_MODE: u32 = 0x53C0;
+const COLOUR: u32 = 0x55B0;
+const MATRIX_COEFFICIENTS: u32 = 0x55B1;
+const BITS_PER_CHANNEL: u32 = 0x55B2;
+const CHROMA_SUBSAMPLING_HORZ: u32 = 0x55B3;
+const CHROMA_SUBSAMPLING_VERT: u32 = 0x55B4;
+const CHROMA_SITING_HORZ: u32 = 0x55B7;
+const CHROMA_SITING_VERT: u32 = 0x55B8;
+const RANGE: u32 = 0x55B9;
+const TRANSFER_CHARACTERISTICS: u32 = 0x55BA;
+const PRIMARIES: u32 = 0x55BB;
+const CLUSTER: u32 = 0x1F43_B675;
+const TIMECODE: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+const BLOCK_GROUP: u32 = 0xA0;
+const BLOCK: u32 = 0xA1;
+const REFERENCE_BLOCK: u32 = 0xFB;
+const BLOCK_ADDITIONS: u32 = 0x75A1;
+const BLOCK_MORE: u32 = 0xA6;
+const BLOCK_ADD_ID: u32 = 0xEE;
+const BLOCK_ADDITIONAL: u32 = 0xA5;
+const CUES: u32 = 0x1C53_BB6B;
+const CUE_POINT: u32 = 0xBB;
+const CUE_TIME: u32 = 0xB3;
+const CUE_TRACK_POSITIONS: u32 = 0xB7;
+const CUE_TRACK: u32 = 0xF7;
+const CUE_CLUSTER_POSITION: u32 = 0xF1;
+
+/// Video codecs supported by the muxer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoCodec {
+    Vp8,
+    Vp9,
+}
+
+impl VideoCodec {
+    fn codec_id(&self) -> &'static str {
+        match self {
+            VideoCodec::Vp8 => "V_VP8",
+            VideoCodec::Vp9 => "V_VP9",
+        }
+    }
+
+    /// Returns the codec features of the track, as in the WebM codec mapping.
+    fn codec_private(&self, track: &VideoTrack) -> Option<Vec<u8>> {
+        match self {
+            VideoCodec::Vp8 => None,
+            VideoCodec::Vp9 => {
+                // Profiles 1 and 3 carry the 4:2:2 and 4:4:4 subsamplings,
+                // profiles 2 and 3 more than 8 bits.
+                let high = (track.bit_depth > 8) as u8;
+                let subsampled = (track.chroma_subsampling != (1, 1)) as u8;
+                let mut features = vec![1, 1, 2 * high + subsampled, 3, 1, track.bit_depth];
+                // The centred 4:2:0 siting of the converter has no value.
+                match track.chroma_subsampling {
+                    (1, 0) => features.extend([4, 1, 2]),
+                    (0, 0) => features.extend([4, 1, 3]),
+                    _ => {}
+                }
+                Some(features)
+            }
+        }
+    }
+}
+
+/// Colour elements of the video track.
+/// Values are numbered as in ISO/IEC 23091-4, except the range:
+/// 1 for the limited (broadcast) range and 2 for the full range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Colour {
+    pub matrix_coefficients: u64,
+    pub range: u64,
+    pub transfer_characteristics: u64,
+    pub primaries: u64,
+}
+
+impl Colour {
+    fn element(&self, track: &VideoTrack) -> Vec<u8> {
+        let mut colour = uint_element(MATRIX_COEFFICIENTS, self.matrix_coefficients);
+        colour.extend(uint_element(BITS_PER_CHANNEL, track.bit_depth.into()));
+        let (horizontal, vertical) = track.chroma_subsampling;
+        colour.extend(uint_element(CHROMA_SUBSAMPLING_HORZ, horizontal.into()));
+        colour.extend(uint_element(CHROMA_SUBSAMPLING_VERT, vertical.into()));
+        // Chroma is sited at the centre of the pixels it covers by the converter.
+        if horizontal > 0 {
+            colour.extend(uint_element(CHROMA_SITING_HORZ, 2));
+        }
+        if vertical > 0 {
+            colour.extend(uint_element(CHROMA_SITING_VERT, 2));
+        }
+        colour.extend(uint_element(RANGE, self.range));
+        colour.extend(uint_element(
+            TRANSFER_CHARACTERISTICS,
+            self.transfer_characteristics,
+        ));
+        colour.extend(uint_element(PRIMARIES, self.primaries));
+        element(COLOUR, &colour)
+    }
+}
+
+/// Description of the video track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoTrack {
+    pub width: u32,
+    pub height: u32,
+    pub codec: VideoCodec,
+    /// Colour space of the frames, left to the players if `None`.
+    pub colour: Option<Colour>,
+    /// Bits per sample of the frames.
+    pub bit_depth: u8,
+    /// Log2 of the chroma subsampling, horizontally and vertically.
+    pub chroma_subsampling: (u8, u8),
+    /// Whether the frames carry an alpha stream in their block additions.
+    pub alpha: bool,
+}
+
+/// Cluster waiting to be written.
+struct Cluster {
+    timecode: u64,
+    blocks: Vec<u8>,
+}
+
+/// Positions of the placeholders patched when a seekable file is finalized.
+struct Placeholders {
+    segment_size: u64,
+    seek_positions: [u64; 3],
+    duration: u64,
+}
+
+/// WebM multiplexer writing into `W`.
+pub struct Muxer<W: Write> {
+    writer: W,
+    seek: Option<SeekFn<W>>,
+    position: u64,
+    segment_start: u64,
+    placeholders: Option<Placeholders>,
+    info_position: u64,
+    tracks_position: u64,
+    cluster: Option<Cluster>,
+    cues: Vec<(u64, u64)>,
+    last_timecode: u64,
+}
+
+impl<W: Write> Muxer<W> {
+    /// Write the file headers and prepare the video track.
+    ///
+    /// # Arguments
+    /// * `writer` - The output.
+    /// * `seek` - How to seek in the output, or `None` to write a live WebM.
+    /// * `track` - The description of the video track.
+    pub fn new(writer: W, seek: Option<SeekFn<W>>, track: &VideoTrack) -> io::Result<Self> {
+        let mut muxer = Muxer {
+            writer,
+            seek,
+            position: 0,
+            segment_start: 0,
+            placeholders: None,
+            info_position: 0,
+            tracks_position: 0,
+            cluster: None,
+            cues: Vec::new(),
+            last_timecode: 0,
+        };
+        muxer.write_headers(track)?;
+        Ok(muxer)
+    }
+
+    /// Returns true if the output is written as a live WebM.
+    pub fn is_live(&self) -> bool {
+        self.seek.is_none()
+    }
+
+    /// Add an encoded frame.
+    ///
+    /// # Arguments
+    /// * `data` - The encoded frame.
+    /// * `timestamp_ns` - The presentation timestamp in nanoseconds.
+    /// * `keyframe` - Whether the frame is a keyframe.
+    pub fn add_frame(&mut self, data: &[u8], timestamp_ns: u64, keyframe: bool) -> io::Result<()> {
+        self.add_block(data, None, timestamp_ns, keyframe)
+    }
+
+    /// Add an encoded frame with the encoded frame of its alpha, for a track
+    /// with alpha.
+    ///
+    /// # Arguments
+    /// * `data` - The encoded frame.
+    /// * `alpha` - The encoded frame of the alpha stream.
+    /// * `timestamp_ns` - The presentation timestamp in nanoseconds.
+    /// * `keyframe` - Whether the frame is a keyframe.
+    pub fn add_frame_with_alpha(
+        &mut self,
+        data: &[u8],
+        alpha: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+    ) -> io::Result<()> {
+        self.add_block(data, Some(alpha), timestamp_ns, keyframe)
+    }
+
+    fn add_block(
+        &mut self,
+        data: &[u8],
+        alpha: Option<&[u8]>,
+        timestamp_ns: u64,
+        keyframe: bool,
+    ) -> io::Result<()> {
+        let timecode = (timestamp_ns + TIMECODE_SCALE / 2) / TIMECODE_SCALE;
+        let start_cluster = match &self.cluster {
+            Some(cluster) => {
+                keyframe || timecode.saturating_sub(cluster.timecode) > i16::MAX as u64
+            }
+            None => true,
+        };
+        if start_cluster {
+            self.flush_cluster()?;
+            if keyframe {
+                self.cues
+                    .push((timecode, self.position - self.segment_start));
+            }
+            self.cluster = Some(Cluster {
+                timecode,
+                blocks: Vec::new(),
+            });
+        }
+        let cluster = self.cluster.as_mut().expect("a cluster is always open");
+        let relative = timecode.saturating_sub(cluster.timecode) as i16;
+        let mut block = Vec::with_capacity(data.len() + 4);
+        block.extend(encode_size(TRACK_NUMBER));
+        block.extend(relative.to_be_bytes());
+        match alpha {
+            None => {
+                block.push(if keyframe { 0x80 } else { 0x00 });
+                block.extend(data);
+                cluster.blocks.extend(element(SIMPLE_BLOCK, &block));
+            }
+            Some(alpha) => {
+                // Block additions need a block group, where only the reference
+                // to the previous frame tells a frame is not a keyframe.
+                block.push(0x00);
+                block.extend(data);
+                let mut group = element(BLOCK, &block);
+                if !keyframe {
+                    let reference = self.last_timecode as i64 - timecode as i64;
+                    group.extend(int_element(REFERENCE_BLOCK, reference));
+                }
+                let mut more = uint_element(BLOCK_ADD_ID, 1);
+                more.extend(element(BLOCK_ADDITIONAL, alpha));
+                group.extend(element(BLOCK_ADDITIONS, &element(BLOCK_MORE, &more)));
+                cluster.blocks.extend(element(BLOCK_GROUP, &group));
+            }
+        }
+        self.last_timecode = timecode;
+        Ok(())
+    }
+
+    /// Write the remaining frames and the index, and return the output.
+    ///
+    /// # Arguments
+    /// * `duration_ns` - The duration of the video in nanoseconds. If not specified,
+    ///   the timestamp of the last frame is used.
+    pub fn finalize(mut self, duration_ns: Option<u64>) -> io::Result<W> {
+        self.flush_cluster()?;
+        if let (Some(seek), Some(placeholders)) = (self.seek, self.placeholders.take()) {
+            let cues_position = self.position;
+            let mut cues = Vec::new();
+            for (timecode, cluster_position) in &self.cues {
+                let mut positions = uint_element(CUE_TRACK, TRACK_NUMBER);
+                positions.extend(uint_element(CUE_CLUSTER_POSITION, *cluster_position));
+                let mut point = uint_element(CUE_TIME, *timecode);
+                point.extend(element(CUE_TRACK_POSITIONS, &positions));
+                cues.extend(element(CUE_POINT, &point));
+            }
+            self.write(&element(CUES, &cues))?;
+            let end = self.position;
+
+            let duration = match duration_ns {
+                Some(ns) => ns as f64 / TIMECODE_SCALE as f64,
+                None => self.last_timecode as f64,
+            };
+            let seek_positions = [
+                self.info_position - self.segment_start,
+                self.tracks_position - self.segment_start,
+                cues_position - self.segment_start,
+            ];
+
+            seek(&mut self.writer, placeholders.segment_size)?;
+            self.writer
+                .write_all(&encode_size_fixed(end - self.segment_start))?;
+            for (field, value) in placeholders.seek_positions.iter().zip(seek_positions) {
+                seek(&mut self.writer, *field)?;
+                self.writer.write_all(&value.to_be_bytes())?;
+            }
+            seek(&mut self.writer, placeholders.duration)?;
+            self.writer.write_all(&duration.to_be_bytes())?;
+            seek(&mut self.writer, end)?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_headers(&mut self, track: &VideoTrack) -> io::Result<()> {
+        let mut header = uint_element(EBML_VERSION, 1);
+        header.extend(uint_element(EBML_READ_VERSION, 1));
+        header.extend(uint_element(EBML_MAX_ID_LENGTH, 4));
+        header.extend(uint_element(EBML_MAX_SIZE_LENGTH, 8));
+        header.extend(string_element(DOC_TYPE, "webm"));
+        header.extend(uint_element(DOC_TYPE_VERSION, 4));
+        header.extend(uint_element(DOC_TYPE_READ_VERSION, 2));
+        self.write(&element(EBML, &header))?;
+
+        self.write(&encode_id(SEGMENT))?;
+        let segment_size = self.position;
+        self.write(&encode_size_fixed(UNKNOWN_SIZE))?;
+        self.segment_start = self.position;
+
+        let mut seek_positions = [0; 3];
+        if !self.is_live() {
+            // Every position is written on 8 bytes so it can be patched in place.
+            let mut entries = Vec::new();
+            let mut offsets = [0; 3];
+            for (index, id) in [INFO, TRACKS, CUES].into_iter().enumerate() {
+                let mut entry = element(SEEK_ID, &encode_id(id));
+                entry.extend(encode_id(SEEK_POSITION));
+                entry.extend(encode_size(8));
+                let seek_header = encode_id(SEEK).len() + encode_size(entry.len() as u64 + 8).len();
+                offsets[index] = entries.len() + seek_header + entry.len();
+                entry.extend(0u64.to_be_bytes());
+                entries.extend(element(SEEK, &entry));
+            }
+            let header = encode_id(SEEK_HEAD).len() + encode_size(entries.len() as u64).len();
+            for (position, offset) in seek_positions.iter_mut().zip(offsets) {
+                *position = self.position + (header + offset) as u64;
+            }
+            self.write(&element(SEEK_HEAD, &entries))?;
+        }
+
+        self.info_position = self.position;
+        let mut info = uint_element(TIMECODE_SCALE_ID, TIMECODE_SCALE);
+        info.extend(string_element(MUXING_APP, APP_NAME));
+        info.extend(string_element(WRITING_APP, APP_NAME));
+        let mut duration = 0;
+        if !self.is_live() {
+            let duration_element = float_element(DURATION, 0.0);
+            let size = (info.len() + duration_element.len()) as u64;
+            let header = encode_id(INFO).len() + encode_size(size).len();
+            duration = self.position + (header + info.len() + duration_element.len() - 8) as u64;
+            info.extend(duration_element);
+        }
+        self.write(&element(INFO, &info))?;
+
+        self.tracks_position = self.position;
+        let mut video = uint_element(PIXEL_WIDTH, track.width.into());
+        video.extend(uint_element(PIXEL_HEIGHT, track.height.into()));
+        if track.alpha {
+            video.extend(uint_element(ALPHA_MODE, 1));
+        }
+        if let Some(colour) = &track.colour {
+            video.extend(colour.element(track));
+        }
+        let mut entry = uint_element(TRACK_NUMBER_ID, TRACK_NUMBER);
+        entry.extend(uint_element(TRACK_UID, TRACK_NUMBER));
+        entry.extend(uint_element(TRACK_TYPE, 1));
+        entry.extend(uint_element(FLAG_LACING, 0));
+        if track.alpha {
+            entry.extend(uint_element(MAX_BLOCK_ADDITION_ID, 1));
+        }
+        entry.extend(string_element(CODEC_ID, track.codec.codec_id()));
+        if let Some(features) = track.codec.codec_private(track) {
+            entry.extend(element(CODEC_PRIVATE, &features));
+        }
+        entry.extend(element(VIDEO, &video));
+        self.write(&element(TRACKS, &element(TRACK_ENTRY, &entry)))?;
+
+        if !self.is_live() {
+            self.placeholders = Some(Placeholders {
+                segment_size,
+                seek_positions,
+                duration,
+            });
+        }
+        Ok(())
+    }
+
+    fn flush_cluster(&mut self) -> io::Result<()> {
+        if let Some(cluster) = self.cluster.take() {
+            let mut content = uint_element(TIMECODE, cluster.timecode);
+            content.extend(cluster.blocks);
+            self.write(&element(CLUSTER, &content))?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.writer.write_all(data)?;
+        self.position += data.len() as u64;
+        Ok(())
+    }
+}
+
+/// Encode an element ID, which already contains its length marker.
+fn encode_id(id: u32) -> Vec<u8> {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count();
+    bytes[skip..].to_vec()
+}
+
+/// Encode an element size on as few bytes as possible.
+fn encode_size(size: u64) -> Vec<u8> {
+    let length = (1..8).find(|l| size < (1 << (7 * l)) - 1).unwrap_or(8);
+    let marked = size | (1 << (7 * length));
+    marked.to_be_bytes()[8 - length..].to_vec()
+}
+
+/// Encode an element size on 8 bytes.
+fn encode_size_fixed(size: u64) -> [u8; 8] {
+    (size | (1 << 56)).to_be_bytes()
+}
+
+fn element(id: u32, payload: &[u8]) -> Vec<u8> {
+    let mut data = encode_id(id);
+    data.extend(encode_size(payload.len() as u64));
+    data.extend(payload);
+    data
+}
+
+fn uint_element(id: u32, value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count().min(7);
+    element(id, &bytes[skip..])
+}
+
+fn int_element(id: u32, value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    // Keep the leading bytes which only extend the sign of the next one.
+    let skip = (0..7)
+        .take_while(|&i| {
+            let extension = if bytes[i + 1] & 0x80 != 0 { 0xFF } else { 0 };
+            bytes[i] == extension
+        })
+        .count();
+    element(id, &bytes[skip..])
+}
+
+fn float_element(id: u32, value: f64) -> Vec<u8> {
+    element(id, &value.to_be_bytes())
+}
+
+fn string_element(id: u32, value: &str) -> Vec<u8> {
+    element(id, value.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{block_times, parse, Node};
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    fn seek(cursor: &mut Cursor<Vec<u8>>, position: u64) -> io::Result<()> {
+        cursor.seek(SeekFrom::Start(position)).map(|_| ())
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(encode_size(0), vec![0x80]);
+        assert_eq!(encode_size(126), vec![0xFE]);
+        assert_eq!(encode_size(127), vec![0x40, 0x7F]);
+        assert_eq!(encode_size(500), vec![0x41, 0xF4]);
+        assert_eq!(
+            encode_size_fixed(UNKNOWN_SIZE),
+            [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
+        );
+    }
+
+    #[test]
+    fn elements() {
+        assert_eq!(uint_element(TRACK_TYPE, 1), vec![0x83, 0x81, 0x01]);
+        assert_eq!(uint_element(TIMECODE, 0), vec![0xE7, 0x81, 0x00]);
+        assert_eq!(int_element(REFERENCE_BLOCK, -4), vec![0xFB, 0x81, 0xFC]);
+        assert_eq!(
+            int_element(REFERENCE_BLOCK, -200),
+            vec![0xFB, 0x82, 0xFF, 0x38]
+        );
+        assert_eq!(
+            int_element(REFERENCE_BLOCK, 200),
+            vec![0xFB, 0x82, 0x00, 0xC8]
+        );
+        assert_eq!(
+            string_element(DOC_TYPE, "webm"),
+            vec![0x42, 0x82, 0x84, b'w', b'e', b'b', b'm']
+        );
+    }
+
+    #[test]
+    fn finalized_file() {
+        let track = VideoTrack {
+            width: 2,
+            height: 2,
+            codec: VideoCodec::Vp9,
+            colour: Some(Colour {
+                matrix_coefficients: 1,
+                range: 1,
+                transfer_characteristics: 1,
+                primaries: 1,
+            }),
+            bit_depth: 8,
+            chroma_subsampling: (1, 1),
+            alpha: false,
+        };
+        let mut muxer = Muxer::new(Cursor::new(Vec::new()), Some(seek), &track).unwrap();
+        muxer.add_frame(&[1, 2, 3], 0, true).unwrap();
+        muxer.add_frame(&[4, 5], 40_000_000, false).unwrap();
+        let data = muxer.finalize(Some(80_000_000)).unwrap().into_inner();
+
+        assert_eq!(&data[..4], &[0x1A, 0x45, 0xDF, 0xA3]);
+        let segment = data
+            .windows(4)
+            .position(|w| w == [0x18, 0x53, 0x80, 0x67])
+            .unwrap();
+        let size = u64::from_be_bytes(data[segment + 4..segment + 12].try_into().unwrap());
+        assert_eq!(size & !(1 << 56), (data.len() - segment - 12) as u64);
+        let duration = data
+            .windows(3)
+            .position(|w| w == [0x44, 0x89, 0x88])
+            .unwrap();
+        let duration = f64::from_be_bytes(data[duration + 3..duration + 11].try_into().unwrap());
+        assert_eq!(duration, 800.0);
+        assert!(data.windows(4).any(|w| w == [0x1C, 0x53, 0xBB, 0x6B]));
+        assert!(data.windows(4).any(|w| w == [0x55, 0xB0, 0x80 | 36, 0x55]));
+        let features = [0x63, 0xA2, 0x86, 1, 1, 0, 3, 1, 8];
+        assert!(data.windows(9).any(|w| w == features));
+        assert!(data.windows(4).any(|w| w == [0x55, 0xB9, 0x81, 0x01]));
+    }
+
+    #[test]
+    fn parse_back() {
+        let track = VideoTrack {
+            width: 2,
+            height: 2,
+            codec: VideoCodec::Vp9,
+            colour: Some(Colour {
+                matrix_coefficients: 1,
+                range: 2,
+                transfer_characteristics: 13,
+                primaries: 1,
+            }),
+            bit_depth: 8,
+            chroma_subsampling: (1, 1),
+            alpha: true,
+        };
+        let mut muxer = Muxer::new(Cursor::new(Vec::new()), Some(seek), &track).unwrap();
+        muxer.add_frame_with_alpha(&[1, 2], &[3], 0, true).unwrap();
+        muxer
+            .add_frame_with_alpha(&[4], &[5, 6], 40_000_000, false)
+            .unwrap();
+        // Too far from the start of its cluster for a relative timecode.
+        muxer
+            .add_frame_with_alpha(&[7], &[8], 4_000_000_000, false)
+            .unwrap();
+        muxer
+            .add_frame_with_alpha(&[9], &[10], 5_000_000_000, true)
+            .unwrap();
+        let data = muxer.finalize(Some(5_040_000_000)).unwrap().into_inner();
+
+        let root = parse(&data, 0, data.len());
+        assert_eq!(
+            root.iter().map(|node| node.id).collect::<Vec<_>>(),
+            [0x1A45_DFA3, 0x1853_8067]
+        );
+        let segment = &root[1];
+        assert_eq!(segment.payload.end, data.len());
+        let clusters: Vec<&Node> = segment.all(0x1F43_B675).collect();
+        assert_eq!(clusters.len(), 3);
+        assert_eq!(
+            segment.ids(),
+            [
+                0x114D_9B74,
+                0x1549_A966,
+                0x1654_AE6B,
+                0x1F43_B675,
+                0x1F43_B675,
+                0x1F43_B675,
+                0x1C53_BB6B
+            ]
+        );
+        let position = |node: &Node| (node.start - segment.payload.start) as u64;
+
+        let seeks: Vec<(u32, u64)> = segment
+            .child(0x114D_9B74)
+            .all(0x4DBB)
+            .map(|seek| {
+                let id = seek.child(0x53AB).uint(&data) as u32;
+                (id, seek.child(0x53AC).uint(&data))
+            })
+            .collect();
+        assert_eq!(seeks.len(), 3);
+        for (id, seek_position) in seeks {
+            assert_eq!(seek_position, position(segment.child(id)));
+        }
+
+        let info = segment.child(0x1549_A966);
+        assert_eq!(info.child(0x2A_D7B1).uint(&data), 100_000);
+        let duration = info.child(0x4489).bytes(&data);
+        assert_eq!(f64::from_be_bytes(duration.try_into().unwrap()), 50_400.0);
+
+        let entry = segment.child(0x1654_AE6B).child(0xAE);
+        assert_eq!(entry.child(0x86).bytes(&data), b"V_VP9");
+        assert_eq!(entry.child(0x63A2).bytes(&data), [1, 1, 0, 3, 1, 8]);
+        assert_eq!(entry.child(0x55EE).uint(&data), 1);
+        let video = entry.child(0xE0);
+        assert_eq!(video.child(0x53C0).uint(&data), 1);
+        let colour = video.child(0x55B0);
+        let values: Vec<(u32, u64)> = colour
+            .children
+            .iter()
+            .map(|node| (node.id, node.uint(&data)))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                (0x55B1, 1),
+                (0x55B2, 8),
+                (0x55B3, 1),
+                (0x55B4, 1),
+                (0x55B7, 2),
+                (0x55B8, 2),
+                (0x55B9, 2),
+                (0x55BA, 13),
+                (0x55BB, 1)
+            ]
+        );
+
+        let times: Vec<Vec<(i64, bool)>> = clusters
+            .iter()
+            .map(|cluster| block_times(&data, cluster))
+            .collect();
+        assert_eq!(
+            times,
+            [
+                vec![(0, true), (400, false)],
+                vec![(40_000, false)],
+                vec![(50_000, true)]
+            ]
+        );
+        let group = clusters[0].all(0xA0).nth(1).unwrap();
+        assert_eq!(group.child(0xFB).int(&data), -400);
+        let more = group.child(0x75A1).child(0xA6);
+        assert_eq!(more.child(0xEE).uint(&data), 1);
+        assert_eq!(more.child(0xA5).bytes(&data), [5, 6]);
+        let group = clusters[1].child(0xA0);
+        assert_eq!(group.child(0xFB).int(&data), -39_600);
+
+        let cues: Vec<(u64, u64)> = segment
+            .child(0x1C53_BB6B)
+            .all(0xBB)
+            .map(|point| {
+                let positions = point.child(0xB7);
+                assert_eq!(positions.child(0xF7).uint(&data), 1);
+                (
+                    point.child(0xB3).uint(&data),
+                    positions.child(0xF1).uint(&data),
+                )
+            })
+            .collect();
+        assert_eq!(
+            cues,
+            [(0, position(clusters[0])), (50_000, position(clusters[2]))]
+        );
+    }
+
+    #[test]
+    fn live_file() {
+        let track = VideoTrack {
+            width: 2,
+            height: 2,
+            codec: VideoCodec::Vp8,
+            colour: None,
+            bit_depth: 8,
+            chroma_subsampling: (1, 1),
+            alpha: false,
+        };
+        let mut muxer = Muxer::new(Vec::new(), None, &track).unwrap();
+        muxer.add_frame(&[1, 2, 3], 0, true).unwrap();
+        let data = muxer.finalize(None).unwrap();
+
+        let segment = data
+            .windows(4)
+            .position(|w| w == [0x18, 0x53, 0x80, 0x67])
+            .unwrap();
+        assert_eq!(
+            &data[segment + 4..segment + 12],
+            &encode_size_fixed(UNKNOWN_SIZE)
+        );
+        assert!(!data.windows(4).any(|w| w == [0x11, 0x4D, 0x9B, 0x74]));
+        assert!(!data.windows(4).any(|w| w == [0x1C, 0x53, 0xBB, 0x6B]));
+        assert!(!data.windows(2).any(|w| w == [0x55, 0xB0]));
+        assert!(!data.windows(2).any(|w| w == [0x63, 0xA2]));
+        assert!(data.ends_with(&[0xA3, 0x87, 0x81, 0x00, 0x00, 0x80, 1, 2, 3]));
+
+        // The segment of unknown size ends with the file.
+        let root = parse(&data, 0, data.len());
+        assert_eq!(root[1].ids(), [0x1549_A966, 0x1654_AE6B, 0x1F43_B675]);
+        let cluster = root[1].child(0x1F43_B675);
+        assert_eq!(block_times(&data, cluster), [(0, true)]);
+    }
+
+    #[test]
+    fn high_bit_depth_422() {
+        let track = VideoTrack {
+            width: 2,
+            height: 2,
+            codec: VideoCodec::Vp9,
+            colour: Some(Colour {
+                matrix_coefficients: 9,
+                range: 1,
+                transfer_characteristics: 16,
+                primaries: 9,
+            }),
+            bit_depth: 10,
+            chroma_subsampling: (1, 0),
+            alpha: false,
+        };
+        let data = Muxer::new(Vec::new(), None, &track)
+            .unwrap()
+            .finalize(None)
+            .unwrap();
+
+        let features = [0x63, 0xA2, 0x89, 1, 1, 3, 3, 1, 10, 4, 1, 2];
+        assert!(data.windows(12).any(|w| w == features));
+        assert!(data.windows(4).any(|w| w == [0x55, 0xB2, 0x81, 10]));
+        assert!(data.windows(4).any(|w| w == [0x55, 0xB3, 0x81, 1]));
+        assert!(data.windows(4).any(|w| w == [0x55, 0xB4, 0x81, 0]));
+        assert!(data.windows(4).any(|w| w == [0x55, 0xB7, 0x81, 2]));
+        assert!(!data.windows(2).any(|w| w == [0x55, 0xB8]));
+    }
+
+    #[test]
+    fn alpha_blocks() {
+        let track = VideoTrack {
+            width: 2,
+            height: 2,
+            codec: VideoCodec::Vp8,
+            colour: None,
+            bit_depth: 8,
+            chroma_subsampling: (1, 1),
+            alpha: true,
+        };
+        let mut muxer = Muxer::new(Vec::new(), None, &track).unwrap();
+        muxer.add_frame_with_alpha(&[1, 2], &[3], 0, true).unwrap();
+        muxer
+            .add_frame_with_alpha(&[4], &[5, 6], 400_000, false)
+            .unwrap();
+        let data = muxer.finalize(None).unwrap();
+
+        assert!(data.windows(4).any(|w| w == [0x55, 0xEE, 0x81, 1]));
+        assert!(data.windows(4).any(|w| w == [0x53, 0xC0, 0x81, 1]));
+        let keyframe = [
+            0xA0, 0x93, 0xA1, 0x86, 0x81, 0x00, 0x00, 0x00, 1, 2, 0x75, 0xA1, 0x88, 0xA6, 0x86,
+            0xEE, 0x81, 1, 0xA5, 0x81, 3,
+        ];
+        assert!(data.windows(keyframe.len()).any(|w| w == keyframe));
+        let frame = [
+            0xA0, 0x96, 0xA1, 0x85, 0x81, 0x00, 0x04, 0x00, 4, 0xFB, 0x81, 0xFC, 0x75, 0xA1, 0x89,
+            0xA6, 0x87, 0xEE, 0x81, 1, 0xA5, 0x82, 5, 6,
+        ];
+        assert!(data.ends_with(&frame));
+    }
+}
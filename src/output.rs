@@ -0,0 +1,254 @@
+//! Contains the handling of the output file.
+
+use serde_derive::Deserialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::Result;
+
+/// What to do when the output file already exists. The CLI keeps existing
+/// files unless asked to overwrite them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputPolicy {
+    /// Replace the existing file.
+    Overwrite,
+    /// Keep the existing file and fail.
+    NoClobber,
+}
+
+/// Output file of a video.
+///
+/// When written atomically, the video goes to a temporary file next to the
+/// destination, which is renamed over it by [OutputFile::commit]. The temporary
+/// file is removed if the output is dropped without being committed, as is the
+/// destination when it was created by the output, so a failed encoding does not
+/// leave a partial video behind.
+pub struct OutputFile {
+    file: File,
+    path: PathBuf,
+    temp_path: Option<PathBuf>,
+    policy: OutputPolicy,
+    created: bool,
+    committed: bool,
+}
+
+impl OutputFile {
+    /// Create the output file.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the output file.
+    /// * `policy` - What to do if the file already exists.
+    /// * `atomic` - Whether to write into a temporary file renamed on commit.
+    ///
+    /// # Errors
+    /// If the file exists and the policy refuses to replace it, [Error::OutputExists]
+    /// is returned.
+    pub fn create<P: AsRef<Path>>(path: P, policy: OutputPolicy, atomic: bool) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let exists = path.exists();
+        if exists && policy == OutputPolicy::NoClobber {
+            return Err(Error::OutputExists(path.display().to_string()));
+        }
+        if exists {
+            warn!("Overwriting {}", path.display());
+        }
+
+        let temp_path = match atomic {
+            true => Some(temp_path(&path)),
+            false => None,
+        };
+        let mut options = OpenOptions::new();
+        options.write(true);
+        match (&temp_path, policy) {
+            (Some(_), _) | (None, OutputPolicy::NoClobber) => options.create_new(true),
+            (None, OutputPolicy::Overwrite) => options.create(true).truncate(true),
+        };
+        let file = match options.open(temp_path.as_ref().unwrap_or(&path)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && temp_path.is_none() => {
+                return Err(Error::OutputExists(path.display().to_string()));
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let created = temp_path.is_none() && !exists;
+        Ok(OutputFile {
+            file,
+            path,
+            temp_path,
+            policy,
+            created,
+            committed: false,
+        })
+    }
+
+    /// Flush the video and move it to its destination.
+    ///
+    /// # Errors
+    /// If a file was created at the destination since the output was created and
+    /// the policy refuses to replace it, [Error::OutputExists] is returned.
+    pub fn commit(mut self) -> Result<()> {
+        self.file.sync_all()?;
+        if let Some(temp_path) = self.temp_path.take() {
+            let moved = match self.policy {
+                OutputPolicy::Overwrite => fs::rename(&temp_path, &self.path),
+                OutputPolicy::NoClobber => move_new(&temp_path, &self.path),
+            };
+            // Left after a failure, or linked to the destination.
+            let _ = fs::remove_file(&temp_path);
+            match moved {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    return Err(Error::OutputExists(self.path.display().to_string()));
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for OutputFile {
+    fn drop(&mut self) {
+        if let Some(temp_path) = &self.temp_path {
+            let _ = fs::remove_file(temp_path);
+        } else if self.created && !self.committed {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for OutputFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+/// Give the file at `from` the path `to`, failing with [io::ErrorKind::AlreadyExists]
+/// instead of replacing a file at `to`, even one created meanwhile. The file is
+/// linked, or copied on file systems without hard links.
+fn move_new(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::hard_link(from, to) {
+        Err(err) if err.kind() != io::ErrorKind::AlreadyExists => {
+            debug!("Copying {} instead of linking it: {}", from.display(), err);
+            let mut file = OpenOptions::new().write(true).create_new(true).open(to)?;
+            io::copy(&mut File::open(from)?, &mut file)?;
+            file.sync_all()
+        }
+        linked => linked,
+    }
+}
+
+/// Returns a hidden temporary path in the directory of `path`.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_dir;
+
+    #[test]
+    fn overwrite_truncates() {
+        let dir = test_dir("overwrite");
+        let path = dir.join("output.webm");
+        fs::write(&path, b"previous longer content").unwrap();
+        let mut output = OutputFile::create(&path, OutputPolicy::Overwrite, false).unwrap();
+        output.write_all(b"new").unwrap();
+        output.commit().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn no_clobber_refuses() {
+        let dir = test_dir("no-clobber");
+        let path = dir.join("output.webm");
+        fs::write(&path, b"previous").unwrap();
+        let output = OutputFile::create(&path, OutputPolicy::NoClobber, false);
+        assert!(matches!(output, Err(Error::OutputExists(_))));
+        assert_eq!(fs::read(&path).unwrap(), b"previous");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_output_removed() {
+        let dir = test_dir("failed");
+        let path = dir.join("output.webm");
+        let mut output = OutputFile::create(&path, OutputPolicy::NoClobber, false).unwrap();
+        output.write_all(b"partial").unwrap();
+        // The encoding fails before the output is committed.
+        drop(output);
+        assert!(!path.exists());
+
+        let mut output = OutputFile::create(&path, OutputPolicy::NoClobber, false).unwrap();
+        output.write_all(b"new").unwrap();
+        output.commit().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn atomic_rename() {
+        let dir = test_dir("atomic");
+        let path = dir.join("output.webm");
+        fs::write(&path, b"previous").unwrap();
+        let mut output = OutputFile::create(&path, OutputPolicy::Overwrite, true).unwrap();
+        output.write_all(b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"previous");
+        output.commit().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn atomic_no_clobber_keeps_new_files() {
+        let dir = test_dir("atomic-no-clobber");
+        let path = dir.join("output.webm");
+        let mut output = OutputFile::create(&path, OutputPolicy::NoClobber, true).unwrap();
+        output.write_all(b"new").unwrap();
+        // Another process creates the destination while the video is written.
+        fs::write(&path, b"concurrent").unwrap();
+        assert!(matches!(output.commit(), Err(Error::OutputExists(_))));
+        assert_eq!(fs::read(&path).unwrap(), b"concurrent");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let other = dir.join("other.webm");
+        let mut output = OutputFile::create(&other, OutputPolicy::NoClobber, true).unwrap();
+        output.write_all(b"new").unwrap();
+        output.commit().unwrap();
+        assert_eq!(fs::read(&other).unwrap(), b"new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn atomic_discarded() {
+        let dir = test_dir("discarded");
+        let path = dir.join("output.webm");
+        let mut output = OutputFile::create(&path, OutputPolicy::Overwrite, true).unwrap();
+        output.write_all(b"new").unwrap();
+        drop(output);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+//! Multi-stage frame processing pipeline.
+//!
+//! Frames are processed by a pool of worker threads and handed back to the
+//! consumer in their original order, so a single ordered stage (the encoder)
+//! can be fed while the workers keep decoding and converting the next frames.
+//! A panic in a worker is resumed on the consumer thread once its frame is due.
+
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, Result as ThreadResult};
+
+use crate::error::Error;
+
+/// Ordered output of a pool of frame workers.
+pub struct Pipeline<T> {
+    results: Receiver<(usize, ThreadResult<Result<T, Error>>)>,
+    slots: Receiver<()>,
+    pending: BTreeMap<usize, ThreadResult<Result<T, Error>>>,
+    next: usize,
+}
+
+impl<T: Send + 'static> Pipeline<T> {
+    /// Start processing the given inputs.
+    ///
+    /// # Arguments
+    /// * `inputs` - The inputs, in presentation order.
+    /// * `jobs` - The number of worker threads.
+    /// * `look_ahead` - The maximum number of processed frames waiting to be consumed.
+    /// * `process` - The function applied to every input by the workers.
+    pub fn new<I, F>(inputs: I, jobs: usize, look_ahead: usize, process: F) -> Self
+    where
+        I: IntoIterator,
+        I::IntoIter: Send + 'static,
+        I::Item: Send + 'static,
+        F: Fn(I::Item) -> Result<T, Error> + Send + Sync + 'static,
+    {
+        let jobs = jobs.max(1);
+        // Every input in flight holds a slot until the consumer takes its result,
+        // which bounds memory use even when one worker falls behind the others.
+        let (slot_sender, slots) = sync_channel(jobs + look_ahead);
+        let (job_sender, job_receiver) = sync_channel::<(usize, I::Item)>(jobs);
+        let (result_sender, results) = sync_channel(jobs + look_ahead);
+
+        let inputs = inputs.into_iter();
+        thread::spawn(move || {
+            for job in inputs.enumerate() {
+                if slot_sender.send(()).is_err() || job_sender.send(job).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let process = Arc::new(process);
+        for _ in 0..jobs {
+            let job_receiver = Arc::clone(&job_receiver);
+            let result_sender = result_sender.clone();
+            let process = Arc::clone(&process);
+            thread::spawn(move || loop {
+                let job = match job_receiver.lock() {
+                    Ok(receiver) => receiver.recv(),
+                    Err(_) => break,
+                };
+                let (index, input) = match job {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+                // A panic is caught so the consumer does not wait forever for its
+                // result, and is resumed there instead.
+                let result = panic::catch_unwind(AssertUnwindSafe(|| process(input)));
+                if result_sender.send((index, result)).is_err() {
+                    break;
+                }
+            });
+        }
+
+        Pipeline {
+            results,
+            slots,
+            pending: BTreeMap::new(),
+            next: 0,
+        }
+    }
+}
+
+impl<T> Iterator for Pipeline<T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(result) = self.pending.remove(&self.next) {
+                self.next += 1;
+                let _ = self.slots.recv();
+                return Some(result.unwrap_or_else(|payload| panic::resume_unwind(payload)));
+            }
+            match self.results.recv() {
+                Ok((index, result)) => {
+                    self.pending.insert(index, result);
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_order() {
+        let inputs: Vec<u64> = (0..100).collect();
+        let pipeline = Pipeline::new(inputs, 8, 2, |i| {
+            thread::sleep(std::time::Duration::from_micros((100 - i) * 10));
+            Ok(i * 2)
+        });
+        let outputs: Vec<u64> = pipeline.map(|r| r.unwrap()).collect();
+        assert_eq!(outputs, (0..100).map(|i| i * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn single_job() {
+        let pipeline = Pipeline::new(vec![1, 2, 3], 1, 0, Ok);
+        let outputs: Vec<i32> = pipeline.map(|r| r.unwrap()).collect();
+        assert_eq!(outputs, vec![1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "worker failed")]
+    fn propagates_panics() {
+        let pipeline = Pipeline::new(0..10, 4, 2, |i| {
+            if i == 5 {
+                panic!("worker failed");
+            }
+            Ok(i)
+        });
+        let outputs: Vec<i32> = pipeline.map(|r| r.unwrap()).collect();
+        assert_eq!(outputs.len(), 10);
+    }
+}
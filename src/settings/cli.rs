@@ -1,21 +1,168 @@
 //! This module handles the command line interface.
 
+use clap::builder::{PossibleValuesParser, TypedValueParser};
 use clap::{Args, Parser, ValueHint};
 use serde_derive::Deserialize;
 
-use crate::settings::{Codec, ScaleAlgorithm};
+use crate::settings::{
+    ChromaFilter, Codec, Color, ColorMatrix, ColorRange, DeadlineMode, DecodeErrorPolicy, FitMode,
+    FrameRate, PixelFormat, RateControlMode, ScaleAlgorithm,
+};
+use crate::sort::SortOrder;
+
+/// Available input options.
+#[derive(Debug, Clone, Args, Deserialize)]
+#[clap(next_help_heading = Some("INPUT OPTIONS"))]
+pub struct InputOptions {
+    /// Order of the images.
+    /// If not specified, natural order is used.
+    #[clap(long, value_enum)]
+    pub sort: Option<SortOrder>,
+
+    /// Reverse the order of the images.
+    #[clap(long, action)]
+    pub reverse: bool,
+
+    /// First index of printf-style sequences.
+    /// If not specified, the first existing file among the indices 0 to 4 is used.
+    #[clap(long)]
+    pub start_number: Option<u64>,
+
+    /// Read the images of the subdirectories of input directories.
+    #[clap(short, long, action)]
+    pub recursive: bool,
+
+    /// Maximum depth of the subdirectories read, 0 being the input directory.
+    /// Implies --recursive.
+    #[clap(long)]
+    pub max_depth: Option<usize>,
+
+    /// Only read the images of input directories matching this glob pattern.
+    /// Patterns without a `/` match the file name, others the path relative to the directory.
+    /// Can be repeated.
+    #[clap(long, value_name = "PATTERN")]
+    pub include: Vec<String>,
+
+    /// Skip the images and subdirectories of input directories matching this glob pattern.
+    /// Can be repeated.
+    #[clap(long, value_name = "PATTERN")]
+    pub exclude: Vec<String>,
+
+    /// Only read images in these formats, detected from their content
+    /// (png, jpeg, gif, webp, tiff, bmp, tga, qoi, pnm, ico, hdr, exr, ...).
+    /// If not specified, every format that can be decoded is read.
+    #[clap(long, value_delimiter = ',')]
+    pub formats: Vec<String>,
+}
 
 /// Available video options.
 #[derive(Debug, Clone, Args, Deserialize)]
 #[clap(next_help_heading = Some("VIDEO OPTIONS"))]
 pub struct VideoOptions {
     /// Bitrate in kilobits per second.
+    /// If not specified, it is estimated from the video size and frame rate.
     #[clap(short, long)]
     pub bitrate: Option<u32>,
 
+    /// How the encoder allocates bits to the frames.
+    /// If not specified, a constant quality is used, constrained by the
+    /// bitrate if one is given, and a bitrate alone gives a variable bitrate.
+    #[clap(long, value_enum)]
+    pub rate_control: Option<RateControlMode>,
+
+    /// Quality level, from 0 (best) to 63, of the constant quality modes.
+    /// If not specified, 31 is used.
+    #[clap(long, visible_alias = "crf", value_parser = clap::value_parser!(u32).range(0..=63))]
+    pub quality: Option<u32>,
+
+    /// Lowest quantizer used by the encoder, from 0 to 63.
+    #[clap(long = "min-q", value_parser = clap::value_parser!(u32).range(0..=63))]
+    pub min_quantizer: Option<u32>,
+
+    /// Highest quantizer used by the encoder, from 0 to 63.
+    #[clap(long = "max-q", value_parser = clap::value_parser!(u32).range(0..=63))]
+    pub max_quantizer: Option<u32>,
+
+    /// Analyse all frames in a first pass before encoding them, for a better
+    /// distribution of the bitrate. The frames are read and converted twice.
+    #[clap(long, action)]
+    pub two_pass: bool,
+
+    /// Encode the frames without loss, with VP9, for archival.
+    /// The rate control and quantizer options are then ignored.
+    #[clap(long, action)]
+    pub lossless: bool,
+
+    /// Layout of the YUV planes.
+    /// If not specified, i420 is used, which all players support.
+    #[clap(long, value_enum)]
+    pub pixel_format: Option<PixelFormat>,
+
+    /// Bits per sample: 8, or 10 and 12 with VP9 for HDR and smoother gradients.
+    /// 16 bits and floating point images keep their precision, but floating point
+    /// values above 1.0 are clipped to white instead of being tone mapped.
+    /// If not specified, 8 bits are used.
+    #[clap(
+        long,
+        value_parser = PossibleValuesParser::new(["8", "10", "12"])
+            .map(|depth| depth.parse::<u32>().unwrap())
+    )]
+    pub bit_depth: Option<u32>,
+
+    /// Keep the transparency of the images, for overlays. The alpha is encoded
+    /// as a second stream, which browsers play as a transparent video.
+    #[clap(long, action)]
+    pub alpha: bool,
+
+    /// Speed of the encoder, from -16 to 16 for VP8 and from -9 to 9 for VP9,
+    /// higher being faster.
+    /// If not specified, 6 is used for VP9 and the default of libvpx for VP8.
+    #[clap(long, allow_hyphen_values = true, value_parser = clap::value_parser!(i32).range(-16..=16))]
+    pub cpu_used: Option<i32>,
+
+    /// Time the encoder may spend on each frame.
+    /// If not specified, realtime is used for a single pass and good for two passes.
+    #[clap(long, value_enum)]
+    pub deadline: Option<DeadlineMode>,
+
+    /// Number of threads used by the encoder.
+    /// If not specified, 8 threads are used.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads: Option<u32>,
+
+    /// Base 2 logarithm of the number of VP9 tile columns, from 0 to 6.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(0..=6))]
+    pub tile_columns: Option<u32>,
+
+    /// Base 2 logarithm of the number of VP9 tile rows, from 0 to 2.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(0..=2))]
+    pub tile_rows: Option<u32>,
+
+    /// Encode the rows of VP9 tiles in parallel.
+    /// If not specified, it is enabled.
+    #[clap(long)]
+    pub row_mt: Option<bool>,
+
+    /// Use hidden alternate reference frames, with VP9.
+    #[clap(long)]
+    pub auto_alt_ref: Option<bool>,
+
+    /// Number of frames the encoder looks ahead, from 0 to 25.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(0..=25))]
+    pub lag_in_frames: Option<u32>,
+
+    /// Lowest number of frames between two keyframes.
+    #[clap(long)]
+    pub min_keyframe_interval: Option<u32>,
+
+    /// Highest number of frames between two keyframes.
+    #[clap(long)]
+    pub max_keyframe_interval: Option<u32>,
+
     /// Frame rate in frames per second.
+    /// Accepts integers, fractions (`30000/1001`) and decimals (`0.25`).
     #[clap(short, long)]
-    pub fps: Option<u64>,
+    pub fps: Option<FrameRate>,
 
     /// Width of the output video.
     /// If not specified, the width of the first image is used.
@@ -27,10 +174,25 @@ pub struct VideoOptions {
     #[clap(long)]
     pub height: Option<u32>,
 
-    /// Name of the video codec to use.
+    /// Do not rotate and flip photos according to their EXIF orientation,
+    /// for images which are already upright.
     #[clap(long, action)]
+    pub no_auto_orient: bool,
+
+    /// How images are fitted into the video size.
+    /// If not specified, images are scaled to fit and padded.
+    #[clap(long, value_enum)]
+    pub fit: Option<FitMode>,
+
+    /// Same as `--fit stretch`.
+    #[clap(long, action, conflicts_with = "fit")]
     pub ignore_aspect_ratio: bool,
 
+    /// Colour of the borders added by `--fit fit`, as a hexadecimal value
+    /// (`#1e90ff`) or a name (black, white, gray).
+    #[clap(long)]
+    pub pad_color: Option<Color>,
+
     /// Name of the video codec to use.
     #[clap(short, long, value_enum)]
     pub codec: Option<Codec>,
@@ -38,24 +200,81 @@ pub struct VideoOptions {
     /// Name of the image scaling algorithm to use.
     #[clap(long, value_enum)]
     pub scaling_algorithm: Option<ScaleAlgorithm>,
+
+    /// Name of the chroma downsampling filter to use.
+    #[clap(long, value_enum)]
+    pub chroma_filter: Option<ChromaFilter>,
+
+    /// Matrix used to convert the images to YUV.
+    /// If not specified, BT.709 is used.
+    #[clap(long, value_enum)]
+    pub color_matrix: Option<ColorMatrix>,
+
+    /// Range of the YUV values.
+    /// If not specified, the limited range expected by most players is used.
+    #[clap(long, value_enum)]
+    pub color_range: Option<ColorRange>,
+
+    /// Number of frames decoded and converted in parallel.
+    /// If not specified, the number of available CPUs is used.
+    #[clap(short, long)]
+    pub jobs: Option<usize>,
+
+    /// What to do with a frame that cannot be decoded.
+    /// If not specified, the encoding stops with an error.
+    #[clap(long, value_enum)]
+    pub on_decode_error: Option<DecodeErrorPolicy>,
 }
 
 /// Simple program to convert a sequence of images to a webm video.
 #[derive(Debug, Clone, Parser, Deserialize)]
 #[clap(author, version, about)]
 pub struct Options {
-    /// Path of the source directory.
-    #[clap(value_hint = ValueHint::DirPath)]
-    pub source_directory: String,
+    /// Directories, images, glob patterns (`shots/*_beauty.png`) or printf-style
+    /// sequences (`frame_%05d.png`) to read the images from, in this order.
+    #[clap(
+        value_name = "INPUTS",
+        value_hint = ValueHint::AnyPath,
+        required_unless_present_any = ["manifest", "file_list"]
+    )]
+    pub inputs: Vec<String>,
+
+    /// Read the paths of the images from a file, one per line, after the inputs.
+    /// Use `-` to read them from the standard input.
+    #[clap(short = 'l', long, value_hint = ValueHint::FilePath)]
+    pub file_list: Option<String>,
+
+    /// Read the images and their durations from a timing manifest
+    /// (TOML, JSON or ffmpeg concat-style text) instead of the inputs.
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
+    pub manifest: Option<String>,
 
     /// Place the output into <output_file>.
+    /// Use `-` to write a live WebM to the standard output.
     #[clap(short, value_hint = ValueHint::FilePath)]
     pub output_file: Option<String>,
 
+    /// Overwrite the output file if it already exists.
+    /// Without this option, an existing output file is kept and the encoding fails.
+    #[clap(short = 'y', long, action, conflicts_with = "no_clobber")]
+    pub overwrite: bool,
+
+    /// Fail instead of overwriting the output file if it already exists.
+    /// This is the default, unless the config file sets another policy.
+    #[clap(short, long, action)]
+    pub no_clobber: bool,
+
+    /// Write into a temporary file, renamed to <output_file> once complete.
+    #[clap(long = "atomic", action)]
+    pub atomic_output: bool,
+
     /// Use verbose output
     #[clap(short, long)]
     pub verbose: Option<String>,
 
+    #[clap(flatten)]
+    pub input_options: InputOptions,
+
     #[clap(flatten)]
     pub video_options: VideoOptions,
 }
@@ -3,7 +3,42 @@
 use serde_derive::Deserialize;
 use std::fs;
 
-use super::{Codec, ScaleAlgorithm, Settings, VideoSettings};
+use crate::output::OutputPolicy;
+use crate::sort::SortOrder;
+
+use super::{
+    ChromaFilter, Codec, Color, ColorMatrix, ColorRange, DeadlineMode, DecodeErrorPolicy, FitMode,
+    FrameRate, InputSettings, PixelFormat, RateControlMode, ScaleAlgorithm, Settings,
+    VideoSettings,
+};
+
+/// Available input settings.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct InputConfig {
+    /// Order of the images.
+    pub sort: Option<SortOrder>,
+
+    /// Reverse the order of the images.
+    pub reverse: Option<bool>,
+
+    /// First index of printf-style sequences.
+    pub start_number: Option<u64>,
+
+    /// Read the images of the subdirectories of input directories.
+    pub recursive: Option<bool>,
+
+    /// Maximum depth of the subdirectories read, 0 being the input directory.
+    pub max_depth: Option<usize>,
+
+    /// Glob patterns the images of input directories must match.
+    pub include: Option<Vec<String>>,
+
+    /// Glob patterns of images and subdirectories of input directories to skip.
+    pub exclude: Option<Vec<String>>,
+
+    /// Names of the image formats read.
+    pub formats: Option<Vec<String>>,
+}
 
 /// Available video settings.
 #[derive(Debug, Clone, Deserialize)]
@@ -11,8 +46,66 @@ pub struct VideoConfig {
     /// Bitrate in kilobits per second.
     pub bitrate: Option<u32>,
 
+    /// Name of the rate control mode.
+    pub rate_control: Option<RateControlMode>,
+
+    /// Quality level, from 0 (best) to 63, of the constant quality modes.
+    pub quality: Option<u32>,
+
+    /// Lowest quantizer used by the encoder, from 0 to 63.
+    pub min_quantizer: Option<u32>,
+
+    /// Highest quantizer used by the encoder, from 0 to 63.
+    pub max_quantizer: Option<u32>,
+
+    /// Analyse all frames in a first pass before encoding them.
+    pub two_pass: Option<bool>,
+
+    /// Encode the frames without loss, with VP9.
+    pub lossless: Option<bool>,
+
+    /// Name of the layout of the YUV planes.
+    pub pixel_format: Option<PixelFormat>,
+
+    /// Bits per sample: 8, or 10 and 12 with VP9.
+    pub bit_depth: Option<u32>,
+
+    /// Keep the transparency of the images.
+    pub alpha: Option<bool>,
+
+    /// Speed of the encoder, from -16 to 16 for VP8 and from -9 to 9 for VP9,
+    /// higher being faster.
+    pub cpu_used: Option<i32>,
+
+    /// Name of the time the encoder may spend on each frame.
+    pub deadline: Option<DeadlineMode>,
+
+    /// Number of threads used by the encoder.
+    pub threads: Option<u32>,
+
+    /// Base 2 logarithm of the number of VP9 tile columns, from 0 to 6.
+    pub tile_columns: Option<u32>,
+
+    /// Base 2 logarithm of the number of VP9 tile rows, from 0 to 2.
+    pub tile_rows: Option<u32>,
+
+    /// Encode the rows of VP9 tiles in parallel.
+    pub row_mt: Option<bool>,
+
+    /// Use hidden alternate reference frames.
+    pub auto_alt_ref: Option<bool>,
+
+    /// Number of frames the encoder looks ahead, from 0 to 25.
+    pub lag_in_frames: Option<u32>,
+
+    /// Lowest number of frames between two keyframes.
+    pub min_keyframe_interval: Option<u32>,
+
+    /// Highest number of frames between two keyframes.
+    pub max_keyframe_interval: Option<u32>,
+
     /// Frame rate in frames per second.
-    pub fps: Option<u64>,
+    pub fps: Option<FrameRate>,
 
     /// Width of the output video.
     pub width: Option<u32>,
@@ -20,28 +113,71 @@ pub struct VideoConfig {
     /// Height of the output video.
     pub height: Option<u32>,
 
-    /// Name of the video codec to use.
+    /// Rotate and flip photos according to their EXIF orientation.
+    pub auto_orient: Option<bool>,
+
+    /// How images are fitted into the video size.
+    pub fit: Option<FitMode>,
+
+    /// Same as a `stretch` fit, kept for older configuration files.
     pub ignore_aspect_ratio: Option<bool>,
 
+    /// Colour of the borders added by the `fit` mode.
+    pub pad_color: Option<Color>,
+
     /// Name of the video codec to use.
     pub codec: Option<Codec>,
 
     /// Name of the image scaling algorithm to use.
     pub scaling_algorithm: Option<ScaleAlgorithm>,
+
+    /// Name of the chroma downsampling filter to use.
+    pub chroma_filter: Option<ChromaFilter>,
+
+    /// Name of the matrix used to convert the images to YUV.
+    pub color_matrix: Option<ColorMatrix>,
+
+    /// Name of the range of the YUV values.
+    pub color_range: Option<ColorRange>,
+
+    /// Number of frames decoded and converted in parallel.
+    pub jobs: Option<usize>,
+
+    /// What to do with a frame that cannot be decoded.
+    pub on_decode_error: Option<DecodeErrorPolicy>,
 }
 
 /// Simple program to convert a sequence of images to a webm video.
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     /// Path of the source directory.
+    /// Kept for older configuration files, `inputs` takes precedence.
     pub source_directory: Option<String>,
 
+    /// Directories, images, glob patterns or printf-style sequences to read the images from.
+    pub inputs: Option<Vec<String>>,
+
+    /// Path of a file listing the images, one per line.
+    pub file_list: Option<String>,
+
+    /// Path of a timing manifest listing the images and their durations.
+    pub manifest: Option<String>,
+
     /// Place the output into <output_file>.
     pub output_file: Option<String>,
 
+    /// What to do when the output file already exists.
+    pub output_policy: Option<OutputPolicy>,
+
+    /// Write into a temporary file, renamed to <output_file> once complete.
+    pub atomic_output: Option<bool>,
+
     /// Use verbose output
     pub verbose: Option<String>,
 
+    #[serde(default)]
+    pub input_settings: InputConfig,
+
     pub video_settings: VideoConfig,
 }
 
@@ -52,11 +188,10 @@ impl Config {
             Ok(home) => format!("{}/.config/img-to-webm/config.toml", home),
             Err(_) => "./config.toml".to_owned(),
         };
-        println!("config_filepath: {}", config_filepath);
+        debug!("Reading config file: {}", config_filepath);
         let config_file = match fs::read_to_string(config_filepath) {
             Ok(file) => file,
             Err(err) => {
-                println!("Error: {}", err);
                 debug!("Couldn't reading config file: {}", err);
                 return None;
             }
@@ -75,24 +210,92 @@ impl Config {
     pub fn into_settings(self) -> Settings {
         let default = Settings::default();
         Settings {
-            source_directory: self.source_directory.unwrap_or(default.source_directory),
+            inputs: self
+                .inputs
+                .or_else(|| self.source_directory.map(|directory| vec![directory]))
+                .unwrap_or(default.inputs),
+            file_list: self.file_list,
+            manifest: self.manifest,
             output_file: self.output_file.unwrap_or(default.output_file),
+            output_policy: self.output_policy.unwrap_or(default.output_policy),
+            atomic_output: self.atomic_output.unwrap_or(default.atomic_output),
             verbose: self.verbose.unwrap_or(default.verbose),
+            input_settings: InputSettings {
+                sort: self
+                    .input_settings
+                    .sort
+                    .unwrap_or(default.input_settings.sort),
+                reverse: self
+                    .input_settings
+                    .reverse
+                    .unwrap_or(default.input_settings.reverse),
+                start_number: self.input_settings.start_number,
+                recursive: self
+                    .input_settings
+                    .recursive
+                    .unwrap_or(default.input_settings.recursive),
+                max_depth: self.input_settings.max_depth,
+                include: self.input_settings.include.unwrap_or_default(),
+                exclude: self.input_settings.exclude.unwrap_or_default(),
+                formats: self.input_settings.formats.unwrap_or_default(),
+            },
             video_settings: VideoSettings {
-                bitrate: self
+                bitrate: self.video_settings.bitrate,
+                rate_control: self.video_settings.rate_control,
+                quality: self.video_settings.quality,
+                min_quantizer: self.video_settings.min_quantizer,
+                max_quantizer: self.video_settings.max_quantizer,
+                two_pass: self
+                    .video_settings
+                    .two_pass
+                    .unwrap_or(default.video_settings.two_pass),
+                lossless: self
+                    .video_settings
+                    .lossless
+                    .unwrap_or(default.video_settings.lossless),
+                pixel_format: self
                     .video_settings
-                    .bitrate
-                    .unwrap_or(default.video_settings.bitrate),
+                    .pixel_format
+                    .unwrap_or(default.video_settings.pixel_format),
+                bit_depth: self
+                    .video_settings
+                    .bit_depth
+                    .unwrap_or(default.video_settings.bit_depth),
+                alpha: self
+                    .video_settings
+                    .alpha
+                    .unwrap_or(default.video_settings.alpha),
+                cpu_used: self.video_settings.cpu_used,
+                deadline: self.video_settings.deadline,
+                threads: self.video_settings.threads,
+                tile_columns: self.video_settings.tile_columns,
+                tile_rows: self.video_settings.tile_rows,
+                row_mt: self.video_settings.row_mt,
+                auto_alt_ref: self.video_settings.auto_alt_ref,
+                lag_in_frames: self.video_settings.lag_in_frames,
+                min_keyframe_interval: self.video_settings.min_keyframe_interval,
+                max_keyframe_interval: self.video_settings.max_keyframe_interval,
                 fps: self
                     .video_settings
                     .fps
                     .unwrap_or(default.video_settings.fps),
                 width: self.video_settings.width,
                 height: self.video_settings.height,
-                ignore_aspect_ratio: self
+                auto_orient: self
                     .video_settings
-                    .ignore_aspect_ratio
-                    .unwrap_or(default.video_settings.ignore_aspect_ratio),
+                    .auto_orient
+                    .unwrap_or(default.video_settings.auto_orient),
+                fit: match self.video_settings.ignore_aspect_ratio {
+                    Some(true) => FitMode::Stretch,
+                    _ => self
+                        .video_settings
+                        .fit
+                        .unwrap_or(default.video_settings.fit),
+                },
+                pad_color: self
+                    .video_settings
+                    .pad_color
+                    .unwrap_or(default.video_settings.pad_color),
                 codec: self
                     .video_settings
                     .codec
@@ -101,7 +304,100 @@ impl Config {
                     .video_settings
                     .scaling_algorithm
                     .unwrap_or(default.video_settings.scaling_algorithm),
+                chroma_filter: self
+                    .video_settings
+                    .chroma_filter
+                    .unwrap_or(default.video_settings.chroma_filter),
+                color_matrix: self
+                    .video_settings
+                    .color_matrix
+                    .unwrap_or(default.video_settings.color_matrix),
+                color_range: self
+                    .video_settings
+                    .color_range
+                    .unwrap_or(default.video_settings.color_range),
+                jobs: self
+                    .video_settings
+                    .jobs
+                    .unwrap_or(default.video_settings.jobs),
+                on_decode_error: self
+                    .video_settings
+                    .on_decode_error
+                    .unwrap_or(default.video_settings.on_decode_error),
             },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ValueEnum;
+    use std::fmt::Debug;
+
+    /// Check that every variant of an enum is read from the configuration file
+    /// under its command line name, and not under its Rust name.
+    fn check_names<T: ValueEnum + PartialEq + Debug>(
+        section: &str,
+        key: &str,
+        value: fn(&Config) -> Option<T>,
+    ) {
+        let parse = |name: &str| {
+            let mut content = format!("[{}]\n{} = \"{}\"\n", section, key, name);
+            if section != "video_settings" {
+                content.push_str("[video_settings]\n");
+            }
+            toml::from_str::<Config>(&content).map(|config| value(&config))
+        };
+        for variant in T::value_variants() {
+            let name = variant.to_possible_value().unwrap().get_name().to_string();
+            assert_eq!(parse(&name).unwrap().as_ref(), Some(variant), "{}", key);
+            let rust_name = format!("{:?}", variant);
+            if rust_name != name {
+                assert!(parse(&rust_name).is_err(), "{} = {}", key, rust_name);
+            }
+        }
+    }
+
+    #[test]
+    fn enum_names() {
+        check_names("input_settings", "sort", |c| c.input_settings.sort);
+        check_names("video_settings", "fit", |c| c.video_settings.fit);
+        check_names("video_settings", "chroma_filter", |c| {
+            c.video_settings.chroma_filter
+        });
+        check_names("video_settings", "color_matrix", |c| {
+            c.video_settings.color_matrix
+        });
+        check_names("video_settings", "color_range", |c| {
+            c.video_settings.color_range
+        });
+        check_names("video_settings", "on_decode_error", |c| {
+            c.video_settings.on_decode_error
+        });
+        check_names("video_settings", "rate_control", |c| {
+            c.video_settings.rate_control
+        });
+        check_names("video_settings", "deadline", |c| c.video_settings.deadline);
+        check_names("video_settings", "pixel_format", |c| {
+            c.video_settings.pixel_format
+        });
+        check_names("video_settings", "codec", |c| {
+            c.video_settings.codec.clone()
+        });
+        check_names("video_settings", "scaling_algorithm", |c| {
+            c.video_settings.scaling_algorithm.clone()
+        });
+
+        for (name, policy) in [
+            ("overwrite", OutputPolicy::Overwrite),
+            ("no-clobber", OutputPolicy::NoClobber),
+        ] {
+            let content = format!("output_policy = \"{}\"\n[video_settings]\n", name);
+            let config: Config = toml::from_str(&content).unwrap();
+            assert_eq!(config.output_policy, Some(policy));
+        }
+        let content = "output_policy = \"NoClobber\"\n[video_settings]\n";
+        assert!(toml::from_str::<Config>(content).is_err());
+    }
+}
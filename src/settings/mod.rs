@@ -1,5 +1,6 @@
 mod cli;
 mod config;
+#[allow(clippy::module_inception)]
 mod settings;
 
 pub use settings::*;
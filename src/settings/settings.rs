@@ -1,16 +1,23 @@
 //! This module contains the settings for the program.
 
 use serde_derive::Deserialize;
-use webm::mux;
+use std::fmt;
+use std::str::FromStr;
 
+use crate::convert::{self, Downsampling};
+use crate::encoder::{self, RateControl};
+use crate::mux::{Colour, VideoCodec};
+use crate::output::OutputPolicy;
 use crate::settings::cli;
+use crate::sort::SortOrder;
 
 use super::config::Config;
 
 /// Available image scaling algorithms.
 /// This enum is used to parse the CLI argument.
 /// See [image::imageops::FilterType] for more information.
-#[derive(clap::ValueEnum, Debug, Clone, Deserialize)]
+#[derive(clap::ValueEnum, Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum ScaleAlgorithm {
     /// Nearest Neighbor
     Nearest,
@@ -24,9 +31,92 @@ pub enum ScaleAlgorithm {
     Lanczos3,
 }
 
+/// Available chroma downsampling filters.
+/// This enum is used to parse the CLI argument.
+/// See [crate::convert::Downsampling] for more information.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChromaFilter {
+    /// Average of each 2x2 block
+    Box,
+    /// Weighted average of the 4x4 pixels around each block, softer
+    Triangle,
+}
+
+/// Available colour matrices.
+/// This enum is used to parse the CLI argument.
+/// See [crate::convert::Matrix] for more information.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMatrix {
+    /// ITU-R BT.601, for standard definition
+    Bt601,
+    /// ITU-R BT.709, for high definition
+    Bt709,
+    /// ITU-R BT.2020, for ultra high definition
+    Bt2020,
+}
+
+/// Available colour ranges.
+/// This enum is used to parse the CLI argument.
+/// See [crate::convert::Range] for more information.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorRange {
+    /// Luma from 16 to 235 and chroma from 16 to 240
+    Limited,
+    /// Luma and chroma from 0 to 255
+    Full,
+}
+
+/// Available rate control modes.
+/// This enum is used to parse the CLI argument.
+/// See [crate::encoder::RateControl] for more information.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateControlMode {
+    /// Constant bitrate, for streaming
+    Cbr,
+    /// Variable bitrate, averaging the bitrate
+    Vbr,
+    /// Constant quality, without exceeding the bitrate
+    Cq,
+    /// Constant quality
+    Q,
+}
+
+/// Available encoding deadlines.
+/// This enum is used to parse the CLI argument.
+/// See [crate::encoder::Deadline] for more information.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeadlineMode {
+    /// As fast as possible, for live encoding
+    Realtime,
+    /// A balance between speed and quality
+    Good,
+    /// The best quality, whatever the time it takes
+    Best,
+}
+
+/// Available pixel formats.
+/// This enum is used to parse the CLI argument.
+/// See [crate::convert::PixelFormat] for more information.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PixelFormat {
+    /// 4:2:0, chroma with half the width and height of the video
+    I420,
+    /// 4:2:2, chroma with half the width of the video, VP9 only
+    I422,
+    /// 4:4:4, chroma with the size of the video, VP9 only
+    I444,
+}
+
 /// Available video codecs.
 /// This enum is used to parse the CLI argument.
-#[derive(clap::ValueEnum, Debug, Clone, Deserialize)]
+#[derive(clap::ValueEnum, Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum Codec {
     /// VP8
     Vp8,
@@ -34,14 +124,339 @@ pub enum Codec {
     Vp9,
 }
 
+/// How images are fitted into the video size.
+/// This enum is used to parse the CLI argument.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FitMode {
+    /// Scale to fit inside the video, padding the remaining borders
+    Fit,
+    /// Scale to cover the video, cropping the overflowing borders
+    Fill,
+    /// Scale to the video size, ignoring the aspect ratio
+    Stretch,
+}
+
+/// An RGB colour.
+/// It is parsed from a hexadecimal value (`#1e90ff`, `#fff`) or a name
+/// (`black`, `white`, `gray`). It is black by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Color(pub [u8; 3]);
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid colour: {}", s);
+        match s.trim().to_lowercase().as_str() {
+            "black" => return Ok(Color([0, 0, 0])),
+            "white" => return Ok(Color([255, 255, 255])),
+            "gray" | "grey" => return Ok(Color([128, 128, 128])),
+            _ => {}
+        }
+        let hex = s.trim().trim_start_matches('#');
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let channel = |digits: &str| u8::from_str_radix(digits, 16).map_err(|_| invalid());
+        match hex.len() {
+            6 => Ok(Color([
+                channel(&hex[0..2])?,
+                channel(&hex[2..4])?,
+                channel(&hex[4..6])?,
+            ])),
+            3 => Ok(Color([
+                channel(&hex[0..1])? * 17,
+                channel(&hex[1..2])? * 17,
+                channel(&hex[2..3])? * 17,
+            ])),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// What to do with a frame that cannot be decoded.
+/// This enum is used to parse the CLI argument.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DecodeErrorPolicy {
+    /// Stop with an error
+    Fail,
+    /// Leave the frame out of the video
+    Skip,
+    /// Show the previous frame again in its place
+    Repeat,
+}
+
+/// Frame rate as an exact fraction of frames per second.
+/// It is parsed from an integer (`30`), a fraction (`30000/1001`) or a decimal
+/// number (`0.25`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "FrameRateValue")]
+pub struct FrameRate {
+    num: u32,
+    den: u32,
+}
+
+/// Frame rate as written in the configuration file.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FrameRateValue {
+    Integer(u64),
+    Float(f64),
+    Text(String),
+}
+
+impl FrameRate {
+    /// Create a frame rate of `num / den` frames per second.
+    ///
+    /// # Errors
+    /// If the numerator or the denominator is zero, or does not fit in the signed
+    /// 32-bit timebase of the encoder once the fraction is reduced, an error is
+    /// returned.
+    pub fn new(num: u32, den: u32) -> Result<Self, String> {
+        let invalid = || format!("Invalid frame rate: {}/{}", num, den);
+        if num == 0 || den == 0 {
+            return Err(invalid());
+        }
+        let divisor = gcd(num, den);
+        let (reduced_num, reduced_den) = (num / divisor, den / divisor);
+        if i32::try_from(reduced_num).is_err() || i32::try_from(reduced_den).is_err() {
+            return Err(invalid());
+        }
+        Ok(FrameRate {
+            num: reduced_num,
+            den: reduced_den,
+        })
+    }
+
+    /// Returns the numerator of the frame rate.
+    pub fn num(&self) -> u32 {
+        self.num
+    }
+
+    /// Returns the denominator of the frame rate.
+    pub fn den(&self) -> u32 {
+        self.den
+    }
+
+    /// Returns the duration of a frame as seconds numerator and denominator, the
+    /// timebase of the encoder. Both fit in 32 bits signed integers, as checked
+    /// by [FrameRate::new].
+    pub fn timebase(&self) -> [i32; 2] {
+        [self.den as i32, self.num as i32]
+    }
+
+    /// Returns the presentation timestamp of a frame in nanoseconds.
+    /// Timestamps are computed from the index, so rounding errors do not accumulate.
+    pub fn timestamp_ns(&self, index: u64) -> u64 {
+        let num = self.num as u128;
+        let ns = index as u128 * 1_000_000_000 * self.den as u128;
+        ((ns + num / 2) / num) as u64
+    }
+}
+
+impl Default for FrameRate {
+    fn default() -> Self {
+        FrameRate { num: 30, den: 1 }
+    }
+}
+
+impl fmt::Display for FrameRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.den {
+            1 => write!(f, "{}", self.num),
+            den => write!(f, "{}/{}", self.num, den),
+        }
+    }
+}
+
+impl FromStr for FrameRate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid frame rate: {}", s);
+        let s = s.trim();
+        if let Some((num, den)) = s.split_once('/') {
+            let num = num.trim().parse().map_err(|_| invalid())?;
+            let den = den.trim().parse().map_err(|_| invalid())?;
+            return FrameRate::new(num, den).map_err(|_| invalid());
+        }
+        let (integer, fraction) = s.split_once('.').unwrap_or((s, ""));
+        if fraction.len() > 9
+            || !(integer.chars().chain(fraction.chars())).all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let den = 10u64.pow(fraction.len() as u32);
+        let num = format!("{}{}", integer, fraction)
+            .parse::<u64>()
+            .map_err(|_| invalid())?;
+        let divisor = gcd64(num, den).max(1);
+        let num = u32::try_from(num / divisor).map_err(|_| invalid())?;
+        let den = u32::try_from(den / divisor).map_err(|_| invalid())?;
+        FrameRate::new(num, den).map_err(|_| invalid())
+    }
+}
+
+impl TryFrom<FrameRateValue> for FrameRate {
+    type Error = String;
+
+    fn try_from(value: FrameRateValue) -> Result<Self, Self::Error> {
+        match value {
+            FrameRateValue::Integer(fps) => fps.to_string().parse(),
+            FrameRateValue::Float(fps) => fps.to_string().parse(),
+            FrameRateValue::Text(fps) => fps.parse(),
+        }
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    gcd64(a.into(), b.into()) as u32
+}
+
+fn gcd64(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Available input settings.
+#[derive(Debug, Clone, Deserialize)]
+pub struct InputSettings {
+    /// Order of the images.
+    pub sort: SortOrder,
+
+    /// Reverse the order of the images.
+    pub reverse: bool,
+
+    /// First index of printf-style sequences.
+    /// If not specified, the first existing file among the indices 0 to 4 is used.
+    pub start_number: Option<u64>,
+
+    /// Read the images of the subdirectories of input directories.
+    pub recursive: bool,
+
+    /// Maximum depth of the subdirectories read, 0 being the input directory.
+    /// If specified, subdirectories are read even if `recursive` is not set.
+    pub max_depth: Option<usize>,
+
+    /// Glob patterns the images of input directories must match, if any.
+    /// Patterns without a `/` match the file name, others the path relative to the directory.
+    pub include: Vec<String>,
+
+    /// Glob patterns of images and subdirectories of input directories to skip.
+    pub exclude: Vec<String>,
+
+    /// Names of the image formats read, like `png` or `jpeg`.
+    /// If empty, every format that can be decoded is read.
+    pub formats: Vec<String>,
+}
+
+impl InputSettings {
+    /// Returns the maximum depth of the subdirectories read, if limited.
+    pub fn depth_limit(&self) -> Option<usize> {
+        match (self.recursive, self.max_depth) {
+            (_, Some(depth)) => Some(depth),
+            (true, None) => None,
+            (false, None) => Some(0),
+        }
+    }
+}
+
+impl Default for InputSettings {
+    fn default() -> Self {
+        InputSettings {
+            sort: SortOrder::Natural,
+            reverse: false,
+            start_number: None,
+            recursive: false,
+            max_depth: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            formats: Vec::new(),
+        }
+    }
+}
+
 /// Available video settings.
 #[derive(Debug, Clone, Deserialize)]
 pub struct VideoSettings {
     /// Bitrate in kilobits per second.
-    pub bitrate: u32,
+    /// If not specified, it is estimated from the video size and frame rate.
+    pub bitrate: Option<u32>,
+
+    /// How the encoder allocates bits to the frames.
+    /// If not specified, it is deduced from the quality and the bitrate.
+    pub rate_control: Option<RateControlMode>,
+
+    /// Quality level, from 0 (best) to 63, of the constant quality modes.
+    pub quality: Option<u32>,
+
+    /// Lowest quantizer used by the encoder, from 0 to 63.
+    pub min_quantizer: Option<u32>,
+
+    /// Highest quantizer used by the encoder, from 0 to 63.
+    pub max_quantizer: Option<u32>,
+
+    /// Analyse all frames in a first pass before encoding them.
+    pub two_pass: bool,
+
+    /// Encode the frames without loss, with VP9.
+    pub lossless: bool,
+
+    /// Layout of the YUV planes.
+    pub pixel_format: PixelFormat,
+
+    /// Bits per sample: 8, or 10 and 12 with VP9.
+    pub bit_depth: u32,
+
+    /// Keep the transparency of the images, encoded as a second stream.
+    pub alpha: bool,
+
+    /// Speed of the encoder, from -16 to 16 for VP8 and from -9 to 9 for VP9,
+    /// higher being faster.
+    pub cpu_used: Option<i32>,
+
+    /// Time the encoder may spend on each frame.
+    pub deadline: Option<DeadlineMode>,
+
+    /// Number of threads used by the encoder.
+    pub threads: Option<u32>,
+
+    /// Base 2 logarithm of the number of VP9 tile columns, from 0 to 6.
+    pub tile_columns: Option<u32>,
+
+    /// Base 2 logarithm of the number of VP9 tile rows, from 0 to 2.
+    pub tile_rows: Option<u32>,
+
+    /// Encode the rows of VP9 tiles in parallel.
+    pub row_mt: Option<bool>,
+
+    /// Use hidden alternate reference frames, with VP9.
+    pub auto_alt_ref: Option<bool>,
+
+    /// Number of frames the encoder looks ahead, from 0 to 25.
+    pub lag_in_frames: Option<u32>,
+
+    /// Lowest number of frames between two keyframes.
+    pub min_keyframe_interval: Option<u32>,
+
+    /// Highest number of frames between two keyframes.
+    pub max_keyframe_interval: Option<u32>,
 
     /// Frame rate in frames per second.
-    pub fps: u64,
+    pub fps: FrameRate,
 
     /// Width of the output video.
     pub width: Option<u32>,
@@ -49,22 +464,102 @@ pub struct VideoSettings {
     /// Height of the output video.
     pub height: Option<u32>,
 
-    /// Name of the video codec to use.
-    pub ignore_aspect_ratio: bool,
+    /// Rotate and flip photos according to their EXIF orientation.
+    pub auto_orient: bool,
+
+    /// How images are fitted into the video size.
+    pub fit: FitMode,
+
+    /// Colour of the borders added by the `fit` mode.
+    pub pad_color: Color,
 
     /// Name of the video codec to use.
     pub codec: Codec,
 
     /// Name of the image scaling algorithm to use.
     pub scaling_algorithm: ScaleAlgorithm,
+
+    /// Name of the chroma downsampling filter to use.
+    pub chroma_filter: ChromaFilter,
+
+    /// Matrix used to convert the images to YUV.
+    pub color_matrix: ColorMatrix,
+
+    /// Range of the YUV values.
+    pub color_range: ColorRange,
+
+    /// Number of frames decoded and converted in parallel.
+    pub jobs: usize,
+
+    /// What to do with a frame that cannot be decoded.
+    pub on_decode_error: DecodeErrorPolicy,
 }
 
 impl VideoSettings {
-    /// Returns the video codec ID as a tuple.
-    pub fn convert_codec(&self) -> (vpx_encode::VideoCodecId, mux::VideoCodecId) {
+    /// Returns the video codec of the WebM track and the encoder.
+    pub fn convert_codec(&self) -> VideoCodec {
         match self.codec {
-            Codec::Vp8 => (vpx_encode::VideoCodecId::VP8, mux::VideoCodecId::VP8),
-            Codec::Vp9 => (vpx_encode::VideoCodecId::VP9, mux::VideoCodecId::VP9),
+            Codec::Vp8 => VideoCodec::Vp8,
+            Codec::Vp9 => VideoCodec::Vp9,
+        }
+    }
+
+    /// Returns the rate control of the encoder for a video of the given size.
+    ///
+    /// Without a mode, a constant quality is used, constrained by the bitrate
+    /// if one is given, and a bitrate alone gives a variable bitrate.
+    /// Without a quality, the default one is brought within the quantizer range.
+    pub fn convert_rate_control(&self, width: u32, height: u32) -> RateControl {
+        let mode = self
+            .rate_control
+            .unwrap_or(match (self.quality, self.bitrate) {
+                (Some(_), Some(_)) => RateControlMode::Cq,
+                (None, Some(_)) => RateControlMode::Vbr,
+                (_, None) => RateControlMode::Q,
+            });
+        // Not `clamp`, which panics on an inverted range, reported by the encoder.
+        let quality = self.quality.unwrap_or_else(|| {
+            DEFAULT_QUALITY
+                .max(self.min_quantizer.unwrap_or(0))
+                .min(self.max_quantizer.unwrap_or(encoder::MAX_QUANTIZER))
+        });
+        let bitrate = self
+            .bitrate
+            .unwrap_or_else(|| estimate_bitrate(width, height, self.fps));
+        match mode {
+            RateControlMode::Cbr => RateControl::Cbr { bitrate },
+            RateControlMode::Vbr => RateControl::Vbr { bitrate },
+            RateControlMode::Cq => RateControl::ConstrainedQuality { quality, bitrate },
+            RateControlMode::Q => RateControl::Quality { quality },
+        }
+    }
+
+    /// Returns the speed and parallelism settings of the encoder.
+    pub fn convert_tuning(&self) -> encoder::Tuning {
+        encoder::Tuning {
+            cpu_used: self.cpu_used,
+            deadline: self.deadline.map(|deadline| match deadline {
+                DeadlineMode::Realtime => encoder::Deadline::Realtime,
+                DeadlineMode::Good => encoder::Deadline::Good,
+                DeadlineMode::Best => encoder::Deadline::Best,
+            }),
+            threads: self.threads,
+            tile_columns: self.tile_columns,
+            tile_rows: self.tile_rows,
+            row_mt: self.row_mt,
+            auto_alt_ref: self.auto_alt_ref,
+            lag_in_frames: self.lag_in_frames,
+            min_keyframe_interval: self.min_keyframe_interval,
+            max_keyframe_interval: self.max_keyframe_interval,
+        }
+    }
+
+    /// Returns the pixel format for the converter and the encoder.
+    pub fn convert_pixel_format(&self) -> convert::PixelFormat {
+        match self.pixel_format {
+            PixelFormat::I420 => convert::PixelFormat::I420,
+            PixelFormat::I422 => convert::PixelFormat::I422,
+            PixelFormat::I444 => convert::PixelFormat::I444,
         }
     }
 
@@ -78,42 +573,142 @@ impl VideoSettings {
             ScaleAlgorithm::Lanczos3 => image::imageops::FilterType::Lanczos3,
         }
     }
+
+    /// Returns the chroma downsampling filter for the converter.
+    pub fn convert_chroma_filter(&self) -> Downsampling {
+        match self.chroma_filter {
+            ChromaFilter::Box => Downsampling::Box,
+            ChromaFilter::Triangle => Downsampling::Triangle,
+        }
+    }
+
+    /// Returns the colour space for the converter.
+    pub fn convert_color_space(&self) -> convert::ColorSpace {
+        convert::ColorSpace {
+            matrix: match self.color_matrix {
+                ColorMatrix::Bt601 => convert::Matrix::Bt601,
+                ColorMatrix::Bt709 => convert::Matrix::Bt709,
+                ColorMatrix::Bt2020 => convert::Matrix::Bt2020,
+            },
+            range: match self.color_range {
+                ColorRange::Limited => convert::Range::Limited,
+                ColorRange::Full => convert::Range::Full,
+            },
+        }
+    }
+
+    /// Returns the Colour element values of the WebM track, as numbered by ISO/IEC 23091-4.
+    pub fn convert_colour(&self) -> Colour {
+        // SMPTE 170M for BT.601, as the primaries of 525 and 625 lines systems differ.
+        let (matrix_coefficients, transfer_characteristics, primaries) = match self.color_matrix {
+            ColorMatrix::Bt601 => (6, 6, 6),
+            ColorMatrix::Bt709 => (1, 1, 1),
+            ColorMatrix::Bt2020 => (9, 14, 9),
+        };
+        Colour {
+            matrix_coefficients,
+            transfer_characteristics,
+            primaries,
+            range: match self.color_range {
+                ColorRange::Limited => 1,
+                ColorRange::Full => 2,
+            },
+        }
+    }
+}
+
+/// Quality level of the constant quality modes when none is given.
+const DEFAULT_QUALITY: u32 = 31;
+
+/// Bitrate in kilobits per second giving about 0.1 bit per pixel, which
+/// suits most videos, and at least 100 kbps for small ones.
+fn estimate_bitrate(width: u32, height: u32, fps: FrameRate) -> u32 {
+    let bits =
+        u64::from(width) * u64::from(height) * u64::from(fps.num()) / u64::from(fps.den()) / 10;
+    (bits / 1000).clamp(100, u64::from(u32::MAX)) as u32
 }
 
 impl Default for VideoSettings {
     fn default() -> Self {
         VideoSettings {
-            bitrate: 25000,
-            fps: 30,
+            bitrate: None,
+            rate_control: None,
+            quality: None,
+            min_quantizer: None,
+            max_quantizer: None,
+            two_pass: false,
+            lossless: false,
+            pixel_format: PixelFormat::I420,
+            bit_depth: 8,
+            alpha: false,
+            cpu_used: None,
+            deadline: None,
+            threads: None,
+            tile_columns: None,
+            tile_rows: None,
+            row_mt: None,
+            auto_alt_ref: None,
+            lag_in_frames: None,
+            min_keyframe_interval: None,
+            max_keyframe_interval: None,
+            fps: FrameRate::default(),
             width: None,
             height: None,
-            ignore_aspect_ratio: false,
+            auto_orient: true,
+            fit: FitMode::Fit,
+            pad_color: Color::default(),
             codec: Codec::Vp9,
             scaling_algorithm: ScaleAlgorithm::Nearest,
+            chroma_filter: ChromaFilter::Box,
+            color_matrix: ColorMatrix::Bt709,
+            color_range: ColorRange::Limited,
+            jobs: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            on_decode_error: DecodeErrorPolicy::Fail,
         }
     }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Settings {
-    /// Path of the source directory.
-    pub source_directory: String,
+    /// Directories, images, glob patterns or printf-style sequences to read the images from.
+    pub inputs: Vec<String>,
+
+    /// Path of a file listing the images, one per line, or `-` for the standard input.
+    pub file_list: Option<String>,
 
-    /// Place the output into <output_file>.
+    /// Path of a timing manifest listing the images and their durations.
+    /// If specified, it is used instead of the inputs.
+    pub manifest: Option<String>,
+
+    /// Place the output into <output_file>, or the standard output if it is `-`.
     pub output_file: String,
 
+    /// What to do when the output file already exists.
+    pub output_policy: OutputPolicy,
+
+    /// Write into a temporary file, renamed to <output_file> once complete.
+    pub atomic_output: bool,
+
     /// Use verbose output
     pub verbose: String,
 
+    pub input_settings: InputSettings,
+
     pub video_settings: VideoSettings,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
-            source_directory: ".".to_string(),
+            inputs: vec![".".to_string()],
+            file_list: None,
+            manifest: None,
             output_file: "output.webm".to_string(),
+            // An existing video is only replaced on request.
+            output_policy: OutputPolicy::NoClobber,
+            atomic_output: false,
             verbose: "WARN".to_string(),
+            input_settings: InputSettings::default(),
             video_settings: VideoSettings::default(),
         }
     }
@@ -129,27 +724,151 @@ impl Settings {
         let cli_options = cli::Options::new();
 
         let settings = Settings {
-            source_directory: cli_options.source_directory.clone(),
+            inputs: match cli_options.inputs.is_empty() && cli_options.file_list.is_none() {
+                true => default_config.inputs,
+                false => cli_options.inputs.clone(),
+            },
+            file_list: match cli_options.inputs.is_empty() {
+                true => cli_options.file_list.clone().or(default_config.file_list),
+                false => cli_options.file_list.clone(),
+            },
+            manifest: cli_options.manifest.clone().or(default_config.manifest),
             output_file: cli_options
                 .output_file
                 .clone()
                 .unwrap_or(default_config.output_file),
+            output_policy: match (cli_options.overwrite, cli_options.no_clobber) {
+                (true, _) => OutputPolicy::Overwrite,
+                (_, true) => OutputPolicy::NoClobber,
+                _ => default_config.output_policy,
+            },
+            atomic_output: cli_options.atomic_output || default_config.atomic_output,
             verbose: cli_options
                 .verbose
                 .clone()
                 .unwrap_or(default_config.verbose),
+            input_settings: InputSettings {
+                sort: cli_options
+                    .input_options
+                    .sort
+                    .unwrap_or(default_config.input_settings.sort),
+                reverse: cli_options.input_options.reverse || default_config.input_settings.reverse,
+                start_number: cli_options
+                    .input_options
+                    .start_number
+                    .or(default_config.input_settings.start_number),
+                recursive: cli_options.input_options.recursive
+                    || default_config.input_settings.recursive,
+                max_depth: cli_options
+                    .input_options
+                    .max_depth
+                    .or(default_config.input_settings.max_depth),
+                include: match cli_options.input_options.include.is_empty() {
+                    true => default_config.input_settings.include,
+                    false => cli_options.input_options.include.clone(),
+                },
+                exclude: match cli_options.input_options.exclude.is_empty() {
+                    true => default_config.input_settings.exclude,
+                    false => cli_options.input_options.exclude.clone(),
+                },
+                formats: match cli_options.input_options.formats.is_empty() {
+                    true => default_config.input_settings.formats,
+                    false => cli_options.input_options.formats.clone(),
+                },
+            },
             video_settings: VideoSettings {
                 bitrate: cli_options
                     .video_options
                     .bitrate
-                    .unwrap_or(default_config.video_settings.bitrate),
+                    .or(default_config.video_settings.bitrate),
+                rate_control: cli_options
+                    .video_options
+                    .rate_control
+                    .or(default_config.video_settings.rate_control),
+                quality: cli_options
+                    .video_options
+                    .quality
+                    .or(default_config.video_settings.quality),
+                min_quantizer: cli_options
+                    .video_options
+                    .min_quantizer
+                    .or(default_config.video_settings.min_quantizer),
+                max_quantizer: cli_options
+                    .video_options
+                    .max_quantizer
+                    .or(default_config.video_settings.max_quantizer),
+                two_pass: cli_options.video_options.two_pass
+                    || default_config.video_settings.two_pass,
+                lossless: cli_options.video_options.lossless
+                    || default_config.video_settings.lossless,
+                pixel_format: cli_options
+                    .video_options
+                    .pixel_format
+                    .unwrap_or(default_config.video_settings.pixel_format),
+                bit_depth: cli_options
+                    .video_options
+                    .bit_depth
+                    .unwrap_or(default_config.video_settings.bit_depth),
+                alpha: cli_options.video_options.alpha || default_config.video_settings.alpha,
+                cpu_used: cli_options
+                    .video_options
+                    .cpu_used
+                    .or(default_config.video_settings.cpu_used),
+                deadline: cli_options
+                    .video_options
+                    .deadline
+                    .or(default_config.video_settings.deadline),
+                threads: cli_options
+                    .video_options
+                    .threads
+                    .or(default_config.video_settings.threads),
+                tile_columns: cli_options
+                    .video_options
+                    .tile_columns
+                    .or(default_config.video_settings.tile_columns),
+                tile_rows: cli_options
+                    .video_options
+                    .tile_rows
+                    .or(default_config.video_settings.tile_rows),
+                row_mt: cli_options
+                    .video_options
+                    .row_mt
+                    .or(default_config.video_settings.row_mt),
+                auto_alt_ref: cli_options
+                    .video_options
+                    .auto_alt_ref
+                    .or(default_config.video_settings.auto_alt_ref),
+                lag_in_frames: cli_options
+                    .video_options
+                    .lag_in_frames
+                    .or(default_config.video_settings.lag_in_frames),
+                min_keyframe_interval: cli_options
+                    .video_options
+                    .min_keyframe_interval
+                    .or(default_config.video_settings.min_keyframe_interval),
+                max_keyframe_interval: cli_options
+                    .video_options
+                    .max_keyframe_interval
+                    .or(default_config.video_settings.max_keyframe_interval),
                 fps: cli_options
                     .video_options
                     .fps
                     .unwrap_or(default_config.video_settings.fps),
                 width: cli_options.video_options.width,
                 height: cli_options.video_options.height,
-                ignore_aspect_ratio: cli_options.video_options.ignore_aspect_ratio,
+                auto_orient: !cli_options.video_options.no_auto_orient
+                    && default_config.video_settings.auto_orient,
+                fit: match cli_options.video_options.ignore_aspect_ratio {
+                    true => FitMode::Stretch,
+                    false => cli_options
+                        .video_options
+                        .fit
+                        .unwrap_or(default_config.video_settings.fit),
+                },
+                pad_color: cli_options
+                    .video_options
+                    .pad_color
+                    .unwrap_or(default_config.video_settings.pad_color),
                 codec: cli_options
                     .video_options
                     .codec
@@ -159,6 +878,26 @@ impl Settings {
                     .video_options
                     .scaling_algorithm
                     .unwrap_or(default_config.video_settings.scaling_algorithm),
+                chroma_filter: cli_options
+                    .video_options
+                    .chroma_filter
+                    .unwrap_or(default_config.video_settings.chroma_filter),
+                color_matrix: cli_options
+                    .video_options
+                    .color_matrix
+                    .unwrap_or(default_config.video_settings.color_matrix),
+                color_range: cli_options
+                    .video_options
+                    .color_range
+                    .unwrap_or(default_config.video_settings.color_range),
+                jobs: cli_options
+                    .video_options
+                    .jobs
+                    .unwrap_or(default_config.video_settings.jobs),
+                on_decode_error: cli_options
+                    .video_options
+                    .on_decode_error
+                    .unwrap_or(default_config.video_settings.on_decode_error),
             },
         };
         settings.set_log_level();
@@ -173,3 +912,117 @@ impl Settings {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_frame_rate() {
+        assert_eq!("30".parse(), FrameRate::new(30, 1));
+        assert_eq!("30000/1001".parse(), FrameRate::new(30000, 1001));
+        assert_eq!("0.25".parse(), FrameRate::new(1, 4));
+        assert_eq!("29.97".parse(), FrameRate::new(2997, 100));
+        assert_eq!("50/2".parse(), FrameRate::new(25, 1));
+        assert!("0".parse::<FrameRate>().is_err());
+        assert!("30/0".parse::<FrameRate>().is_err());
+        assert!("-1".parse::<FrameRate>().is_err());
+        assert!("fast".parse::<FrameRate>().is_err());
+        assert!(FrameRate::new(u32::MAX, 1).is_err());
+        assert!(format!("1/{}", u32::MAX).parse::<FrameRate>().is_err());
+        assert!("2147483648".parse::<FrameRate>().is_err());
+        assert_eq!(
+            FrameRate::new(u32::MAX - 1, 2),
+            FrameRate::new(i32::MAX as u32, 1)
+        );
+        assert_eq!(
+            FrameRate::new(30000, 1001).unwrap().timebase(),
+            [1001, 30000]
+        );
+    }
+
+    #[test]
+    fn timestamps_do_not_drift() {
+        let fps = FrameRate::new(30, 1).unwrap();
+        assert_eq!(fps.timestamp_ns(1), 33_333_333);
+        assert_eq!(fps.timestamp_ns(900), 30_000_000_000);
+        let ntsc = FrameRate::new(30000, 1001).unwrap();
+        assert_eq!(ntsc.timestamp_ns(30000), 1_001_000_000_000);
+        let slow = FrameRate::new(1, 4).unwrap();
+        assert_eq!(slow.timestamp_ns(3), 12_000_000_000);
+    }
+
+    #[test]
+    fn parse_color() {
+        assert_eq!("#1e90ff".parse(), Ok(Color([0x1e, 0x90, 0xff])));
+        assert_eq!("FFF".parse(), Ok(Color([255, 255, 255])));
+        assert_eq!("Black".parse(), Ok(Color([0, 0, 0])));
+        assert!("#12345".parse::<Color>().is_err());
+        assert!("#gggggg".parse::<Color>().is_err());
+        assert!("+12345".parse::<Color>().is_err());
+    }
+
+    #[test]
+    fn rate_control() {
+        let settings = |quality, bitrate, rate_control| VideoSettings {
+            quality,
+            bitrate,
+            rate_control,
+            fps: FrameRate::new(30, 1).unwrap(),
+            ..VideoSettings::default()
+        };
+        let convert = |settings: VideoSettings| settings.convert_rate_control(1920, 1080);
+        assert_eq!(
+            convert(settings(None, None, None)),
+            RateControl::Quality { quality: 31 }
+        );
+        assert_eq!(
+            convert(settings(None, Some(2000), None)),
+            RateControl::Vbr { bitrate: 2000 }
+        );
+        assert_eq!(
+            convert(settings(Some(20), Some(2000), None)),
+            RateControl::ConstrainedQuality {
+                quality: 20,
+                bitrate: 2000
+            }
+        );
+        assert_eq!(
+            convert(settings(None, None, Some(RateControlMode::Cbr))),
+            RateControl::Cbr { bitrate: 6220 }
+        );
+        let small = settings(None, None, Some(RateControlMode::Vbr));
+        assert_eq!(
+            small.convert_rate_control(64, 48),
+            RateControl::Vbr { bitrate: 100 }
+        );
+    }
+
+    #[test]
+    fn default_quality_within_quantizers() {
+        let settings = |min_quantizer, max_quantizer| VideoSettings {
+            min_quantizer,
+            max_quantizer,
+            ..VideoSettings::default()
+        };
+        let quality = |settings: VideoSettings| settings.convert_rate_control(64, 48);
+        assert_eq!(
+            quality(settings(None, Some(20))),
+            RateControl::Quality { quality: 20 }
+        );
+        assert_eq!(
+            quality(settings(Some(40), None)),
+            RateControl::Quality { quality: 40 }
+        );
+        assert_eq!(
+            quality(settings(Some(10), Some(50))),
+            RateControl::Quality { quality: 31 }
+        );
+        // An explicit quality is kept, and checked by the encoder.
+        let explicit = VideoSettings {
+            quality: Some(31),
+            ..settings(None, Some(20))
+        };
+        assert_eq!(quality(explicit), RateControl::Quality { quality: 31 });
+    }
+}
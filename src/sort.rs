@@ -0,0 +1,252 @@
+//! Contains the ordering of input images.
+
+use serde_derive::Deserialize;
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Available orders for the input images.
+/// This enum is used to parse the CLI argument.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    /// Natural order, numbers in names are compared by value (frame_2 before frame_10)
+    Natural,
+    /// Lexical order of the names
+    Lexical,
+    /// Modification time of the files
+    Modified,
+    /// EXIF DateTimeOriginal of the photos, images without it come last
+    ExifDate,
+}
+
+/// Sort image paths.
+///
+/// # Arguments
+/// * `paths` - The paths to sort.
+/// * `order` - The order to sort them in.
+/// * `reverse` - Whether to reverse the order.
+pub fn sort_paths(paths: &mut [PathBuf], order: SortOrder, reverse: bool) {
+    // The sorts are stable, so ties keep the natural order.
+    paths.sort_by(|a, b| natural_cmp(a, b));
+    match order {
+        SortOrder::Natural => {}
+        SortOrder::Lexical => paths.sort(),
+        SortOrder::Modified => paths.sort_by_cached_key(|path| modified_time(path)),
+        SortOrder::ExifDate => paths.sort_by_cached_key(|path| {
+            // Images without a date go last.
+            let date = exif_date(path);
+            (date.is_none(), date)
+        }),
+    }
+    if reverse {
+        paths.reverse();
+    }
+}
+
+/// Compare two paths, comparing runs of digits by their numeric value.
+pub fn natural_cmp(a: &Path, b: &Path) -> Ordering {
+    let a = a.to_string_lossy();
+    let b = b.to_string_lossy();
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(&b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x_digits = take_digits(&mut a_chars);
+                let y_digits = take_digits(&mut b_chars);
+                let x_value = x_digits.trim_start_matches('0');
+                let y_value = y_digits.trim_start_matches('0');
+                let ordering = x_value
+                    .len()
+                    .cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+fn take_digits<I: Iterator<Item = char>>(chars: &mut std::iter::Peekable<I>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        digits.push(c);
+    }
+    digits
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    path.metadata().and_then(|m| m.modified()).ok()
+}
+
+/// Returns the EXIF DateTimeOriginal of a photo, with its sub-seconds if any,
+/// as a string that sorts chronologically.
+fn exif_date(path: &Path) -> Option<String> {
+    let file = File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+    let field = exif.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)?;
+    let mut date = match &field.value {
+        exif::Value::Ascii(values) => String::from_utf8_lossy(values.first()?).into_owned(),
+        _ => return None,
+    };
+    if let Some(field) = exif.get_field(exif::Tag::SubSecTimeOriginal, exif::In::PRIMARY) {
+        if let exif::Value::Ascii(values) = &field.value {
+            if let Some(sub_seconds) = values.first() {
+                date.push('.');
+                date.push_str(String::from_utf8_lossy(sub_seconds).trim());
+            }
+        }
+    }
+    debug!("{} was taken on {}", path.display(), date);
+    Some(date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{jpeg_with_exif, test_dir};
+    use image::DynamicImage;
+    use std::fs;
+    use std::time::Duration;
+
+    fn sorted(names: &[&str], order: SortOrder, reverse: bool) -> Vec<String> {
+        let mut paths: Vec<PathBuf> = names.iter().map(PathBuf::from).collect();
+        sort_paths(&mut paths, order, reverse);
+        paths.iter().map(|p| p.display().to_string()).collect()
+    }
+
+    #[test]
+    fn natural() {
+        let names = [
+            "frame_10.png",
+            "frame_2.png",
+            "frame_1.png",
+            "frame_02.png",
+            "a.png",
+        ];
+        assert_eq!(
+            sorted(&names, SortOrder::Natural, false),
+            vec![
+                "a.png",
+                "frame_1.png",
+                "frame_02.png",
+                "frame_2.png",
+                "frame_10.png"
+            ]
+        );
+    }
+
+    #[test]
+    fn lexical_reversed() {
+        let names = ["frame_10.png", "frame_2.png", "frame_1.png"];
+        assert_eq!(
+            sorted(&names, SortOrder::Lexical, true),
+            vec!["frame_2.png", "frame_10.png", "frame_1.png"]
+        );
+    }
+
+    #[test]
+    fn natural_long_numbers() {
+        let names = [
+            "IMG_20230101_120000.jpg",
+            "IMG_9.jpg",
+            "IMG_20221231_235959.jpg",
+        ];
+        assert_eq!(
+            sorted(&names, SortOrder::Natural, false),
+            vec![
+                "IMG_9.jpg",
+                "IMG_20221231_235959.jpg",
+                "IMG_20230101_120000.jpg"
+            ]
+        );
+    }
+
+    /// Returns the names of the files of `dir` sorted by `order`.
+    fn sorted_files(dir: &Path, order: SortOrder) -> Vec<String> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        sort_paths(&mut paths, order, false);
+        paths
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn modified() {
+        let dir = test_dir("sort-modified");
+        let epoch = SystemTime::UNIX_EPOCH;
+        for (name, seconds) in [("a.png", 300), ("b.png", 100), ("c.png", 200)] {
+            let file = File::create(dir.join(name)).unwrap();
+            file.set_modified(epoch + Duration::from_secs(seconds))
+                .unwrap();
+        }
+        assert_eq!(
+            sorted_files(&dir, SortOrder::Modified),
+            vec!["b.png", "c.png", "a.png"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn exif_dates() {
+        let dir = test_dir("sort-exif");
+        let image = DynamicImage::new_rgb8(1, 1);
+        let ascii = |tag, value: &str| exif::Field {
+            tag,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Ascii(vec![value.as_bytes().to_vec()]),
+        };
+        let date = |value| ascii(exif::Tag::DateTimeOriginal, value);
+        let sub_seconds = |value| ascii(exif::Tag::SubSecTimeOriginal, value);
+        let photos = [
+            ("IMG_1.jpg", vec![date("2023:05:01 10:00:00")]),
+            (
+                "IMG_2.jpg",
+                vec![date("2023:05:01 09:00:00"), sub_seconds("50")],
+            ),
+            (
+                "IMG_3.jpg",
+                vec![date("2023:05:01 09:00:00"), sub_seconds("25")],
+            ),
+            ("IMG_5.jpg", vec![date("2022:12:31 23:59:59")]),
+        ];
+        for (name, fields) in photos {
+            fs::write(dir.join(name), jpeg_with_exif(&image, &fields)).unwrap();
+        }
+        // Photos without a date come last, in natural order.
+        image.save(dir.join("IMG_4.jpg")).unwrap();
+        fs::write(dir.join("IMG_0.jpg"), b"not an image").unwrap();
+        assert_eq!(
+            sorted_files(&dir, SortOrder::ExifDate),
+            vec![
+                "IMG_5.jpg",
+                "IMG_3.jpg",
+                "IMG_2.jpg",
+                "IMG_1.jpg",
+                "IMG_0.jpg",
+                "IMG_4.jpg"
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+//! Fixtures shared by the unit tests.
+
+use image::{DynamicImage, ImageFormat};
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// Returns an empty temporary directory, unique to the test `name` and the
+/// test process.
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("img-to-video-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Returns a JPEG file of `image` with the given EXIF fields.
+pub(crate) fn jpeg_with_exif(image: &DynamicImage, fields: &[exif::Field]) -> Vec<u8> {
+    let mut jpeg = Vec::new();
+    image
+        .write_to(&mut io::Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+        .unwrap();
+    let mut writer = exif::experimental::Writer::new();
+    for field in fields {
+        writer.push_field(field);
+    }
+    let mut tiff = io::Cursor::new(Vec::new());
+    writer.write(&mut tiff, false).unwrap();
+    let tiff = tiff.into_inner();
+
+    // Insert an APP1 segment right after the start of image marker.
+    let mut segment = vec![0xff, 0xe1];
+    segment.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+    segment.extend_from_slice(b"Exif\0\0");
+    segment.extend_from_slice(&tiff);
+    jpeg.splice(2..2, segment);
+    jpeg
+}
+
+/// Element parsed back from a WebM file, independently of the writer.
+pub(crate) struct Node {
+    pub(crate) id: u32,
+    pub(crate) start: usize,
+    pub(crate) payload: Range<usize>,
+    pub(crate) children: Vec<Node>,
+}
+
+impl Node {
+    pub(crate) fn child(&self, id: u32) -> &Node {
+        self.all(id).next().expect("missing child element")
+    }
+
+    pub(crate) fn all(&self, id: u32) -> impl Iterator<Item = &Node> {
+        self.children.iter().filter(move |node| node.id == id)
+    }
+
+    pub(crate) fn ids(&self) -> Vec<u32> {
+        self.children.iter().map(|node| node.id).collect()
+    }
+
+    pub(crate) fn bytes<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.payload.clone()]
+    }
+
+    pub(crate) fn uint(&self, data: &[u8]) -> u64 {
+        self.bytes(data)
+            .iter()
+            .fold(0, |value, byte| value << 8 | *byte as u64)
+    }
+
+    pub(crate) fn int(&self, data: &[u8]) -> i64 {
+        let bytes = self.bytes(data);
+        let shift = 64 - 8 * bytes.len() as u32;
+        ((self.uint(data) << shift) as i64) >> shift
+    }
+}
+
+/// Read a variable size integer, returning its value with or without the
+/// length marker, and its length.
+fn read_vint(data: &[u8], position: usize, keep_marker: bool) -> (u64, usize) {
+    let length = data[position].leading_zeros() as usize + 1;
+    assert!(length <= 8, "invalid variable size integer at {}", position);
+    let mut value = data[position..position + length]
+        .iter()
+        .fold(0, |value, byte| value << 8 | *byte as u64);
+    if !keep_marker {
+        value &= (1 << (7 * length)) - 1;
+    }
+    (value, length)
+}
+
+/// Parse the elements filling `data[start..end]` exactly. An element of
+/// unknown size, as the segment of a live file, extends up to `end`.
+pub(crate) fn parse(data: &[u8], start: usize, end: usize) -> Vec<Node> {
+    const MASTERS: [u32; 16] = [
+        0x1A45_DFA3,
+        0x1853_8067,
+        0x114D_9B74,
+        0x4DBB,
+        0x1549_A966,
+        0x1654_AE6B,
+        0xAE,
+        0xE0,
+        0x55B0,
+        0x1F43_B675,
+        0xA0,
+        0x75A1,
+        0xA6,
+        0x1C53_BB6B,
+        0xBB,
+        0xB7,
+    ];
+    let mut nodes = Vec::new();
+    let mut position = start;
+    while position < end {
+        let (id, id_length) = read_vint(data, position, true);
+        let (size, size_length) = read_vint(data, position + id_length, false);
+        let payload = position + id_length + size_length;
+        let payload = match size == (1 << (7 * size_length)) - 1 {
+            true => payload..end,
+            false => payload..payload + size as usize,
+        };
+        assert!(payload.end <= end, "element {:X} overflows its parent", id);
+        let id = id as u32;
+        let children = match MASTERS.contains(&id) {
+            true => parse(data, payload.start, payload.end),
+            false => Vec::new(),
+        };
+        nodes.push(Node {
+            id,
+            start: position,
+            payload: payload.clone(),
+            children,
+        });
+        position = payload.end;
+    }
+    assert_eq!(position, end, "elements do not fill their parent");
+    nodes
+}
+
+/// Returns the absolute timecodes and keyframe flags of the blocks of a
+/// cluster, whether they are simple blocks or in block groups.
+pub(crate) fn block_times(data: &[u8], cluster: &Node) -> Vec<(i64, bool)> {
+    let timecode = cluster.child(0xE7).uint(data) as i64;
+    cluster
+        .children
+        .iter()
+        .filter_map(|node| {
+            let (block, key) = match node.id {
+                0xA3 => {
+                    let block = node.bytes(data);
+                    let (_, length) = read_vint(block, 0, false);
+                    (block, block[length + 2] & 0x80 != 0)
+                }
+                0xA0 => (
+                    node.child(0xA1).bytes(data),
+                    node.all(0xFB).next().is_none(),
+                ),
+                _ => return None,
+            };
+            let (track, length) = read_vint(block, 0, false);
+            assert_eq!(track, 1);
+            let relative = i16::from_be_bytes([block[length], block[length + 1]]);
+            Some((timecode + relative as i64, key))
+        })
+        .collect()
+}
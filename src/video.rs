@@ -0,0 +1,1094 @@
+//! Encoding of a frame source into a WebM video.
+
+use image::{imageops, DynamicImage, ImageBuffer, Pixel, Rgba, RgbaImage};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::convert;
+use crate::encoder::{self, Encoder, Packet, Pass};
+use crate::error::Error;
+use crate::images::{Frame, FrameSource};
+use crate::mux::{Muxer, SeekFn, VideoTrack};
+use crate::pipeline::Pipeline;
+use crate::settings::{Color, DecodeErrorPolicy, FitMode, FrameRate, VideoSettings};
+use crate::Result;
+
+/// Maximum number of decoded frames kept in memory ahead of the encoder.
+const FRAME_LOOK_AHEAD: usize = 4;
+
+/// Builder for encoding a sequence of images into a WebM video.
+///
+/// # Example
+/// ```no_run
+/// use img_to_video::images::FrameSource;
+/// use img_to_video::settings::{InputSettings, VideoSettings};
+/// use img_to_video::VideoBuilder;
+///
+/// let output = std::io::Cursor::new(Vec::new());
+/// let video = VideoBuilder::new(output)
+///     .settings(VideoSettings::default())
+///     .frames(FrameSource::from_directory("frames", &InputSettings::default())?)
+///     .encode()?
+///     .into_inner();
+/// # Ok::<(), img_to_video::Error>(())
+/// ```
+pub struct VideoBuilder<W: Write> {
+    settings: VideoSettings,
+    frames: Option<FrameSource>,
+    output: W,
+    seek: Option<SeekFn<W>>,
+}
+
+impl<W: Write + Seek> VideoBuilder<W> {
+    /// Create a builder writing a finalized video into `output`.
+    pub fn new(output: W) -> Self {
+        VideoBuilder {
+            settings: VideoSettings::default(),
+            frames: None,
+            output,
+            seek: Some(seek_to::<W>),
+        }
+    }
+}
+
+impl<W: Write> VideoBuilder<W> {
+    /// Create a builder writing a live video into an output that cannot seek,
+    /// such as a pipe. The video has neither a duration nor an index.
+    pub fn live(output: W) -> Self {
+        VideoBuilder {
+            settings: VideoSettings::default(),
+            frames: None,
+            output,
+            seek: None,
+        }
+    }
+
+    /// Set the video settings.
+    pub fn settings(mut self, settings: VideoSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Set the frames to encode.
+    pub fn frames(mut self, frames: FrameSource) -> Self {
+        self.frames = Some(frames);
+        self
+    }
+
+    /// Encode all frames, write the video and return the output.
+    ///
+    /// Frames which cannot be decoded are handled according to
+    /// [VideoSettings::on_decode_error], and listed at the end of the encoding.
+    ///
+    /// # Errors
+    /// If the frame source is empty, if a frame cannot be decoded and the policy
+    /// is to fail, or if the encoder or the output fails, an error is returned.
+    pub fn encode(self) -> Result<W> {
+        let settings = self.settings;
+        let policy = settings.on_decode_error;
+        let mut failed = FailedFrames::default();
+        let mut frames = self
+            .frames
+            .unwrap_or_else(|| FrameSource::from_paths(Vec::new()));
+        // The stream takes the size of the first frame which can be read.
+        let (first_width, first_height) = loop {
+            let dimensions = match frames.peek() {
+                Some(first) => first.input.dimensions(settings.auto_orient),
+                None => return Err(Error::NoImages(frames.origin().to_string())),
+            };
+            match dimensions {
+                Ok(dimensions) => break dimensions,
+                Err(err @ Error::Decode { .. }) if policy != DecodeErrorPolicy::Fail => {
+                    failed.skipped(err);
+                    frames.next();
+                }
+                Err(err) => return Err(err),
+            }
+        };
+        // Frames are fitted into the requested size, and their edges repeated up
+        // to the even size of the stream.
+        let size = (
+            settings.width.unwrap_or(first_width),
+            settings.height.unwrap_or(first_height),
+        );
+        let (width, height) = even_size(size.0, size.1);
+
+        let (x_shift, y_shift) = settings.convert_pixel_format().chroma_shift();
+        let track = VideoTrack {
+            width,
+            height,
+            codec: settings.convert_codec(),
+            colour: Some(settings.convert_colour()),
+            bit_depth: settings.bit_depth as u8,
+            chroma_subsampling: (x_shift as u8, y_shift as u8),
+            alpha: settings.alpha,
+        };
+        let config = encoder::Config {
+            width,
+            height,
+            // One tick per frame, the timeline maps ticks to actual timestamps.
+            timebase: settings.fps.timebase(),
+            codec: settings.convert_codec(),
+            rate_control: settings.convert_rate_control(width, height),
+            min_quantizer: settings.min_quantizer,
+            max_quantizer: settings.max_quantizer,
+            pass: Pass::Single,
+            pixel_format: settings.convert_pixel_format(),
+            bit_depth: settings.bit_depth,
+            lossless: settings.lossless,
+            color_space: settings.convert_color_space(),
+            tuning: settings.convert_tuning(),
+            // The alpha frames can only be decoded from the keyframes of their stream.
+            fixed_keyframes: settings.alpha,
+        };
+        // The alpha is encoded as the luma of a second stream.
+        let alpha_config = settings.alpha.then(|| encoder::Config {
+            pixel_format: convert::PixelFormat::I420,
+            bit_depth: 8,
+            // The alpha values are written as they are, from 0 to 255.
+            color_space: convert::ColorSpace {
+                range: convert::Range::Full,
+                ..config.color_space
+            },
+            ..config.clone()
+        });
+        let (pass, alpha_pass) = match settings.two_pass {
+            true => {
+                let replay = frames.replay().ok_or_else(|| {
+                    Error::EncoderCustom(
+                        "Two-pass encoding needs frames which can be read twice".to_string(),
+                    )
+                })?;
+                let (stats, alpha_stats) = first_pass(
+                    replay,
+                    &settings,
+                    size,
+                    config.clone(),
+                    alpha_config.clone(),
+                )?;
+                (Pass::Last(stats), alpha_stats.map(Pass::Last))
+            }
+            false => (Pass::Single, None),
+        };
+        let frame_size = config.frame_size();
+        let mut vpx = Encoder::new(encoder::Config { pass, ..config })?;
+        let mut alpha_vpx = alpha_config
+            .map(|config| {
+                Encoder::new(encoder::Config {
+                    pass: alpha_pass.unwrap_or(Pass::Single),
+                    ..config
+                })
+            })
+            .transpose()?;
+        let mut webm = Muxer::new(self.output, self.seek, &track)?;
+
+        info!("Start encoding images...");
+        let mut timeline = Timeline::new(settings.fps);
+        let mut pending = PendingFrames::new(settings.alpha);
+        let origin = frames.origin().to_string();
+        let ticks = encode_frames(
+            frames,
+            &settings,
+            size,
+            &mut failed,
+            |tick, duration, frame| {
+                timeline.push(tick, duration);
+                let (yuv, alpha) = frame.split_at(frame_size);
+                vpx.encode(tick as i64, yuv)?
+                    .for_each(|packet| pending.push(packet));
+                if let Some(alpha_vpx) = &mut alpha_vpx {
+                    alpha_vpx
+                        .encode(tick as i64, alpha)?
+                        .for_each(|packet| pending.push_alpha(packet));
+                }
+                pending.write(&mut webm, &mut timeline, false)
+            },
+        )?;
+        if ticks == 0 {
+            return Err(Error::NoImages(origin));
+        }
+
+        info!("Finished encoding images.");
+
+        info!("Start writing webm...");
+        flush(&mut vpx, |packet| {
+            pending.push(packet);
+            Ok(())
+        })?;
+        if let Some(alpha_vpx) = &mut alpha_vpx {
+            flush(alpha_vpx, |packet| {
+                pending.push_alpha(packet);
+                Ok(())
+            })?;
+        }
+        pending.write(&mut webm, &mut timeline, true)?;
+
+        let output = webm.finalize(Some(timeline.end()))?;
+        info!("Finished writing webm.");
+        failed.report();
+        Ok(output)
+    }
+}
+
+/// Run the first pass of a two-pass encoding, and return the statistics of
+/// the colour stream and of the alpha stream if there is one.
+fn first_pass(
+    frames: FrameSource,
+    settings: &VideoSettings,
+    size: (u32, u32),
+    config: encoder::Config,
+    alpha_config: Option<encoder::Config>,
+) -> Result<(Vec<u8>, Option<Vec<u8>>)> {
+    info!("Start analysing images...");
+    let frame_size = config.frame_size();
+    let first = |config| {
+        Encoder::new(encoder::Config {
+            pass: Pass::First,
+            ..config
+        })
+    };
+    let mut vpx = first(config)?;
+    let mut alpha_vpx = alpha_config.map(first).transpose()?;
+    let (mut stats, mut alpha_stats) = (Vec::new(), Vec::new());
+    // The frames which cannot be decoded are reported by the last pass.
+    let mut failed = FailedFrames::default();
+    encode_frames(frames, settings, size, &mut failed, |tick, _, frame| {
+        let (yuv, alpha) = frame.split_at(frame_size);
+        vpx.encode(tick as i64, yuv)?
+            .for_each(|packet| collect_stats(&mut stats, packet));
+        if let Some(alpha_vpx) = &mut alpha_vpx {
+            alpha_vpx
+                .encode(tick as i64, alpha)?
+                .for_each(|packet| collect_stats(&mut alpha_stats, packet));
+        }
+        Ok(())
+    })?;
+    flush(&mut vpx, |packet| {
+        collect_stats(&mut stats, packet);
+        Ok(())
+    })?;
+    if let Some(alpha_vpx) = &mut alpha_vpx {
+        flush(alpha_vpx, |packet| {
+            collect_stats(&mut alpha_stats, packet);
+            Ok(())
+        })?;
+    }
+    info!("Finished analysing images.");
+    Ok((stats, alpha_vpx.map(|_| alpha_stats)))
+}
+
+fn collect_stats(stats: &mut Vec<u8>, packet: Packet) {
+    if let Packet::Stats(data) = packet {
+        stats.extend_from_slice(data);
+    }
+}
+
+/// Decode and convert all frames, fitted into the given size, and give them to
+/// `encode` with their tick.
+/// Frames which cannot be decoded are handled according to the settings.
+/// Returns the number of frames given to `encode`.
+fn encode_frames<F>(
+    frames: FrameSource,
+    settings: &VideoSettings,
+    (width, height): (u32, u32),
+    failed: &mut FailedFrames,
+    mut encode: F,
+) -> Result<u64>
+where
+    F: FnMut(u64, Option<Duration>, &[u8]) -> Result<()>,
+{
+    let policy = settings.on_decode_error;
+    let total = frames.frame_count();
+    let video_settings = settings.clone();
+    let yuv_frames = Pipeline::new(
+        frames,
+        settings.jobs,
+        FRAME_LOOK_AHEAD,
+        move |frame: Frame| {
+            // Decode errors are handed to the encoding loop, which knows the
+            // previous frame, unless they stop the encoding anyway.
+            let yuv = match frame.input.load(video_settings.auto_orient) {
+                Ok(image) => Ok(prepare_frame(image, width, height, &video_settings)),
+                Err(err @ Error::Decode { .. }) if policy != DecodeErrorPolicy::Fail => Err(err),
+                Err(err) => return Err(err),
+            };
+            Ok((yuv, frame.duration))
+        },
+    );
+    let mut previous: Option<Vec<u8>> = None;
+    let mut tick = 0;
+    for (index, yuv) in yuv_frames.enumerate() {
+        let (yuv, duration) = yuv?;
+        let yuv = match (yuv, previous.take()) {
+            (Ok(yuv), _) => yuv,
+            (Err(err), Some(previous)) if policy == DecodeErrorPolicy::Repeat => {
+                failed.repeated(err);
+                previous
+            }
+            (Err(err), _) => {
+                // Without a previous frame, there is nothing to repeat.
+                failed.skipped(err);
+                continue;
+            }
+        };
+        match total {
+            Some(total) => info!(
+                "Encoding images {:.1}%",
+                index as f32 / total as f32 * 100.0
+            ),
+            None => info!("Encoding image {}", index + 1),
+        }
+        encode(tick, duration, &yuv)?;
+        tick += 1;
+        if policy == DecodeErrorPolicy::Repeat {
+            previous = Some(yuv);
+        }
+    }
+    Ok(tick)
+}
+
+/// Flush the frames delayed by the encoder and give their packets to `output`.
+fn flush<F>(vpx: &mut Encoder, mut output: F) -> Result<()>
+where
+    F: FnMut(Packet) -> Result<()>,
+{
+    loop {
+        let mut empty = true;
+        for packet in vpx.flush()? {
+            empty = false;
+            output(packet)?;
+        }
+        if empty {
+            return Ok(());
+        }
+    }
+}
+
+/// An encoded frame, copied out of the encoder.
+struct EncodedFrame {
+    data: Vec<u8>,
+    key: bool,
+    pts: i64,
+}
+
+impl EncodedFrame {
+    fn new(packet: Packet) -> Option<Self> {
+        match packet {
+            Packet::Frame(frame) => Some(EncodedFrame {
+                data: frame.data.to_vec(),
+                key: frame.key,
+                pts: frame.pts,
+            }),
+            Packet::Stats(_) => None,
+        }
+    }
+}
+
+/// Encoded frames waiting to be added to the video. With alpha, a frame is
+/// added once the alpha stream has encoded the frame with the same tick.
+struct PendingFrames {
+    /// Frames of the colour stream.
+    frames: VecDeque<EncodedFrame>,
+    /// Frames of the alpha stream, if there is one.
+    alpha: Option<VecDeque<EncodedFrame>>,
+}
+
+impl PendingFrames {
+    fn new(alpha: bool) -> Self {
+        PendingFrames {
+            frames: VecDeque::new(),
+            alpha: alpha.then(VecDeque::new),
+        }
+    }
+
+    /// Queue a packet of the colour stream.
+    fn push(&mut self, packet: Packet) {
+        self.frames.extend(EncodedFrame::new(packet));
+    }
+
+    /// Queue a packet of the alpha stream.
+    fn push_alpha(&mut self, packet: Packet) {
+        if let Some(alpha) = &mut self.alpha {
+            alpha.extend(EncodedFrame::new(packet));
+        }
+    }
+
+    /// Add the frames encoded by all streams to the video, at the timestamp of
+    /// their tick. Once the encoders are flushed, `finished` adds the frames
+    /// left without alpha.
+    fn write<W: Write>(
+        &mut self,
+        webm: &mut Muxer<W>,
+        timeline: &mut Timeline,
+        finished: bool,
+    ) -> Result<()> {
+        while let Some(frame) = self.frames.front() {
+            let pts = frame.pts;
+            let alpha = match &mut self.alpha {
+                Some(alpha) => {
+                    // Alpha frames without a colour frame cannot be shown.
+                    while alpha.front().is_some_and(|alpha| alpha.pts < pts) {
+                        warn!("Dropping the alpha of a frame without colour");
+                        alpha.pop_front();
+                    }
+                    match alpha.front() {
+                        Some(alpha_frame) if alpha_frame.pts == pts => alpha.pop_front(),
+                        None if !finished => break,
+                        _ => {
+                            warn!("Adding a frame without alpha");
+                            None
+                        }
+                    }
+                }
+                None => None,
+            };
+            let frame = self.frames.pop_front().expect("a frame is queued");
+            let timestamp = timeline.timestamp(pts as u64);
+            match alpha {
+                // Seeking to the block must decode both frames.
+                Some(alpha) => webm.add_frame_with_alpha(
+                    &frame.data,
+                    &alpha.data,
+                    timestamp,
+                    frame.key && alpha.key,
+                )?,
+                None => webm.add_frame(&frame.data, timestamp, frame.key)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Frames which could not be decoded, reported at the end of the encoding.
+#[derive(Default)]
+struct FailedFrames {
+    skipped: Vec<PathBuf>,
+    repeated: Vec<PathBuf>,
+}
+
+impl FailedFrames {
+    /// Record a frame left out of the video.
+    fn skipped(&mut self, err: Error) {
+        warn!("{}, skipping the frame", err);
+        self.skipped.extend(failed_path(err));
+    }
+
+    /// Record a frame replaced by the previous one.
+    fn repeated(&mut self, err: Error) {
+        warn!("{}, repeating the previous frame", err);
+        self.repeated.extend(failed_path(err));
+    }
+
+    /// Log the summary of the frames which could not be decoded, if any.
+    fn report(&self) {
+        for (paths, action) in [
+            (&self.skipped, "skipped"),
+            (&self.repeated, "replaced by the previous frame"),
+        ] {
+            if paths.is_empty() {
+                continue;
+            }
+            warn!(
+                "{} frames could not be decoded and were {}:",
+                paths.len(),
+                action
+            );
+            for path in paths {
+                warn!("  {}", path.display());
+            }
+        }
+    }
+}
+
+fn failed_path(err: Error) -> Option<PathBuf> {
+    match err {
+        Error::Decode { path, .. } => Some(path),
+        _ => None,
+    }
+}
+
+/// Presentation timestamps of the encoded frames.
+///
+/// The encoder counts one tick per frame, whatever its duration. The timeline
+/// maps those ticks to timestamps in nanoseconds. Frames without a duration of
+/// their own are placed with the frame rate, so their timestamps do not drift.
+struct Timeline {
+    fps: FrameRate,
+    explicit_ns: u64,
+    implicit_frames: u64,
+    pending: BTreeMap<u64, u64>,
+}
+
+impl Timeline {
+    fn new(fps: FrameRate) -> Self {
+        Timeline {
+            fps,
+            explicit_ns: 0,
+            implicit_frames: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Add the next frame, sent to the encoder with the given tick.
+    fn push(&mut self, tick: u64, duration: Option<Duration>) {
+        self.pending.insert(tick, self.end());
+        match duration {
+            Some(duration) => self.explicit_ns += duration.as_nanos() as u64,
+            None => self.implicit_frames += 1,
+        }
+    }
+
+    /// Returns the timestamp of a frame returned by the encoder.
+    fn timestamp(&mut self, tick: u64) -> u64 {
+        self.pending = self.pending.split_off(&tick);
+        self.pending
+            .get(&tick)
+            .copied()
+            .unwrap_or_else(|| self.end())
+    }
+
+    /// Returns the end of the last frame.
+    fn end(&self) -> u64 {
+        self.explicit_ns + self.fps.timestamp_ns(self.implicit_frames)
+    }
+}
+
+fn seek_to<W: Seek>(writer: &mut W, position: u64) -> io::Result<()> {
+    writer.seek(SeekFrom::Start(position)).map(|_| ())
+}
+
+/// Scale an image to the given size and convert it to YUV, followed by the
+/// YUV420 frame of its alpha if the alpha is kept.
+/// An odd size is rounded up to the even size of the video by repeating the last
+/// column or row of the image, so the returned frame always has the size of the
+/// video.
+fn prepare_frame(
+    image: DynamicImage,
+    width: u32,
+    height: u32,
+    video_settings: &VideoSettings,
+) -> Vec<u8> {
+    let filter = video_settings.convert_scaling_algorithm();
+    let resized_img = match width != image.width() || height != image.height() {
+        true => match video_settings.fit {
+            FitMode::Fit => pad(
+                image.resize(width, height, filter),
+                width,
+                height,
+                video_settings.pad_color,
+                video_settings.alpha,
+                video_settings.bit_depth,
+            ),
+            FitMode::Fill => image.resize_to_fill(width, height, filter),
+            FitMode::Stretch => image.resize_exact(width, height, filter),
+        },
+        false => image,
+    };
+    let resized_img = extend_edges(resized_img, round_to_even(width), round_to_even(height));
+    let (width, height) = (resized_img.width(), resized_img.height());
+    let converted;
+    let pixels = match convert::Pixels::from_image(&resized_img) {
+        Some(pixels) => pixels,
+        None => {
+            converted = match video_settings.bit_depth {
+                8 => DynamicImage::ImageRgba8(resized_img.into_rgba8()),
+                _ => DynamicImage::ImageRgba16(resized_img.into_rgba16()),
+            };
+            convert::Pixels::from_image(&converted).expect("RGBA pixels are supported")
+        }
+    };
+    let format = video_settings.convert_pixel_format();
+    let filter = video_settings.convert_chroma_filter();
+    let color_space = video_settings.convert_color_space();
+    let mut frame = match video_settings.bit_depth {
+        8 => convert::convert_to_yuv(width, height, pixels, format, filter, color_space),
+        // The encoder reads the samples in native endianness.
+        bit_depth => convert::convert_to_yuv_high(
+            width,
+            height,
+            pixels,
+            format,
+            bit_depth,
+            filter,
+            color_space,
+        )
+        .into_iter()
+        .flat_map(u16::to_ne_bytes)
+        .collect(),
+    };
+    if video_settings.alpha {
+        frame.extend(convert::convert_alpha_to_yuv420(width, height, pixels));
+    }
+    frame
+}
+
+/// Round a video size up to even dimensions, as required by the encoder.
+fn even_size(width: u32, height: u32) -> (u32, u32) {
+    let even = (round_to_even(width), round_to_even(height));
+    if even != (width, height) {
+        warn!(
+            "The video size must be even, using {}x{} instead of {}x{} by repeating the edges of the frames",
+            even.0, even.1, width, height
+        );
+    }
+    even
+}
+
+fn round_to_even(value: u32) -> u32 {
+    value + value % 2
+}
+
+/// Extend an image to the given size by repeating its last column and row,
+/// keeping the pixel type of the image.
+fn extend_edges(image: DynamicImage, width: u32, height: u32) -> DynamicImage {
+    if image.width() == width && image.height() == height {
+        return image;
+    }
+    match image {
+        DynamicImage::ImageLuma8(image) => repeat_edges(&image, width, height).into(),
+        DynamicImage::ImageLumaA8(image) => repeat_edges(&image, width, height).into(),
+        DynamicImage::ImageRgb8(image) => repeat_edges(&image, width, height).into(),
+        DynamicImage::ImageRgba8(image) => repeat_edges(&image, width, height).into(),
+        DynamicImage::ImageLuma16(image) => repeat_edges(&image, width, height).into(),
+        DynamicImage::ImageLumaA16(image) => repeat_edges(&image, width, height).into(),
+        DynamicImage::ImageRgb16(image) => repeat_edges(&image, width, height).into(),
+        DynamicImage::ImageRgba16(image) => repeat_edges(&image, width, height).into(),
+        DynamicImage::ImageRgb32F(image) => repeat_edges(&image, width, height).into(),
+        image => repeat_edges(&image.into_rgba32f(), width, height).into(),
+    }
+}
+
+fn repeat_edges<P: Pixel>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    width: u32,
+    height: u32,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let (last_x, last_y) = (image.width() - 1, image.height() - 1);
+    ImageBuffer::from_fn(width, height, |x, y| {
+        *image.get_pixel(x.min(last_x), y.min(last_y))
+    })
+}
+
+/// Center an image on a canvas of the given size and colour, which is
+/// transparent if the alpha is kept. With more than 8 bits, the canvas keeps
+/// the precision of 16 bits and floating point images.
+fn pad(
+    image: DynamicImage,
+    width: u32,
+    height: u32,
+    color: Color,
+    alpha: bool,
+    bit_depth: u32,
+) -> DynamicImage {
+    if image.width() == width && image.height() == height {
+        return image;
+    }
+    let [r, g, b] = color.0;
+    let opacity = if alpha { 0 } else { u8::MAX };
+    let canvas = DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+        width,
+        height,
+        Rgba([r, g, b, opacity]),
+    ));
+    let position = ((width - image.width()) / 2, (height - image.height()) / 2);
+    let float = matches!(
+        image,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+    match (bit_depth > 8, float, alpha) {
+        (false, _, false) => overlay(canvas.into_rgb8(), &image.into_rgb8(), position).into(),
+        (false, _, true) => overlay(canvas.into_rgba8(), &image.into_rgba8(), position).into(),
+        (true, false, false) => overlay(canvas.into_rgb16(), &image.into_rgb16(), position).into(),
+        (true, false, true) => overlay(canvas.into_rgba16(), &image.into_rgba16(), position).into(),
+        (true, true, false) => overlay(canvas.into_rgb32f(), &image.into_rgb32f(), position).into(),
+        (true, true, true) => {
+            overlay(canvas.into_rgba32f(), &image.into_rgba32f(), position).into()
+        }
+    }
+}
+
+/// Returns `canvas` with `image` drawn at `position`.
+fn overlay<P: Pixel>(
+    mut canvas: ImageBuffer<P, Vec<P::Subpixel>>,
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    (x, y): (u32, u32),
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    imageops::overlay(&mut canvas, image, x.into(), y.into());
+    canvas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::images::FrameInput;
+    use crate::test_utils::{block_times, parse, test_dir, Node};
+    use image::{GrayImage, ImageFormat, Luma, Rgb, RgbImage};
+    use std::fs;
+    use std::io::Cursor;
+
+    /// Returns the luma row of a prepared frame.
+    fn luma_row(yuv: &[u8], width: u32, row: u32) -> &[u8] {
+        let start = (row * width) as usize;
+        &yuv[start..start + width as usize]
+    }
+
+    #[test]
+    fn frames_match_the_video_size() {
+        let white = DynamicImage::ImageRgb8(RgbImage::from_pixel(8, 4, Rgb([255, 255, 255])));
+        for fit in [FitMode::Fit, FitMode::Fill, FitMode::Stretch] {
+            let settings = VideoSettings {
+                fit,
+                ..VideoSettings::default()
+            };
+            for (width, height) in [(8, 8), (4, 8), (16, 4), (6, 2), (7, 3)] {
+                let yuv = prepare_frame(white.clone(), width, height, &settings);
+                let (width, height) = even_size(width, height);
+                assert_eq!(yuv.len(), convert::yuv420_size(width, height));
+            }
+        }
+    }
+
+    #[test]
+    fn stream_size_is_even() {
+        assert_eq!(even_size(1920, 1080), (1920, 1080));
+        assert_eq!(even_size(1919, 1079), (1920, 1080));
+        assert_eq!(even_size(1, 2), (2, 2));
+    }
+
+    #[test]
+    fn odd_sizes_repeat_the_edges() {
+        // Columns from black to white, which a resampling would blend.
+        let gradient = GrayImage::from_fn(7, 3, |x, _| Luma([(x * 40) as u8]));
+        for fit in [FitMode::Fit, FitMode::Fill, FitMode::Stretch] {
+            let settings = VideoSettings {
+                fit,
+                ..VideoSettings::default()
+            };
+            let yuv = prepare_frame(DynamicImage::ImageLuma8(gradient.clone()), 7, 3, &settings);
+            let reference = prepare_frame(
+                DynamicImage::ImageLuma8(extend_edges_of(&gradient)),
+                8,
+                4,
+                &settings,
+            );
+            assert_eq!(yuv, reference);
+            for row in 0..4 {
+                let row = luma_row(&yuv, 8, row);
+                assert_eq!(row[6], row[7]);
+                assert!(row.windows(2).take(6).all(|w| w[0] < w[1]));
+            }
+        }
+    }
+
+    /// Returns the 8x4 image made by repeating the last column and row of a 7x3 image.
+    fn extend_edges_of(image: &GrayImage) -> GrayImage {
+        GrayImage::from_fn(8, 4, |x, y| *image.get_pixel(x.min(6), y.min(2)))
+    }
+
+    #[test]
+    fn fit_pads_with_color() {
+        let white = DynamicImage::ImageRgb8(RgbImage::from_pixel(8, 4, Rgb([255, 255, 255])));
+        let settings = VideoSettings::default();
+        let yuv = prepare_frame(white, 8, 8, &settings);
+        assert!(luma_row(&yuv, 8, 0).iter().all(|&y| y < 32));
+        assert!(luma_row(&yuv, 8, 4).iter().all(|&y| y > 224));
+        assert!(luma_row(&yuv, 8, 7).iter().all(|&y| y < 32));
+    }
+
+    #[test]
+    fn fit_pads_with_transparency() {
+        let red = DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 4, Rgba([255, 0, 0, 128])));
+        let settings = VideoSettings {
+            alpha: true,
+            ..VideoSettings::default()
+        };
+        let frame = prepare_frame(red, 8, 8, &settings);
+        let size = convert::yuv420_size(8, 8);
+        assert_eq!(frame.len(), 2 * size);
+        let alpha = &frame[size..];
+        assert!(luma_row(alpha, 8, 0).iter().all(|&a| a == 0));
+        assert!(luma_row(alpha, 8, 4).iter().all(|&a| a == 128));
+        assert!(alpha[64..].iter().all(|&c| c == 128));
+    }
+
+    fn frame(data: &[u8], key: bool, pts: i64) -> Packet<'_> {
+        Packet::Frame(encoder::Frame { data, key, pts })
+    }
+
+    /// Returns a live muxer with alpha, and a timeline of `ticks` frames at 25 fps.
+    fn alpha_output(ticks: u64) -> (Muxer<Vec<u8>>, Timeline) {
+        let track = VideoTrack {
+            width: 2,
+            height: 2,
+            codec: crate::mux::VideoCodec::Vp9,
+            colour: None,
+            bit_depth: 8,
+            chroma_subsampling: (1, 1),
+            alpha: true,
+        };
+        let mut timeline = Timeline::new(FrameRate::new(25, 1).unwrap());
+        for tick in 0..ticks {
+            timeline.push(tick, None);
+        }
+        (Muxer::new(Vec::new(), None, &track).unwrap(), timeline)
+    }
+
+    #[test]
+    fn alpha_frames_are_paired() {
+        let (mut webm, mut timeline) = alpha_output(2);
+        let mut pending = PendingFrames::new(true);
+        pending.push(frame(&[1], true, 0));
+        pending.push(frame(&[2], false, 1));
+        pending.push_alpha(frame(&[3], true, 0));
+        pending.write(&mut webm, &mut timeline, false).unwrap();
+        assert_eq!(pending.frames.len(), 1);
+        pending.push_alpha(Packet::Stats(&[4]));
+        pending.push_alpha(frame(&[5], false, 1));
+        pending.write(&mut webm, &mut timeline, false).unwrap();
+        assert!(pending.frames.is_empty());
+        let data = webm.finalize(None).unwrap();
+        // The alpha of the second frame follows its colour.
+        assert!(data.ends_with(&[0xEE, 0x81, 1, 0xA5, 0x81, 5]));
+    }
+
+    #[test]
+    fn alpha_keyframes_need_both_streams() {
+        let (mut webm, mut timeline) = alpha_output(5);
+        let mut pending = PendingFrames::new(true);
+        for (data, key, pts) in [(10, true, 0), (11, true, 1), (12, false, 2), (14, false, 4)] {
+            pending.push(frame(&[data], key, pts));
+        }
+        for (data, key, pts) in [(20, true, 0), (21, false, 1), (23, true, 3), (24, true, 4)] {
+            pending.push_alpha(frame(&[data], key, pts));
+        }
+        pending.write(&mut webm, &mut timeline, false).unwrap();
+        assert!(pending.frames.is_empty());
+        let data = webm.finalize(None).unwrap();
+
+        // Only the first frame is a keyframe in both streams.
+        let clusters = data.windows(4).filter(|w| w == &[0x1F, 0x43, 0xB6, 0x75]);
+        assert_eq!(clusters.count(), 1);
+        // The second frame references the first one, 40 ms before.
+        let second = [0xFB, 0x82, 0xFE, 0x70, 0x75, 0xA1, 0x88, 0xA6, 0x86];
+        assert!(data.windows(second.len()).any(|w| w == second));
+        assert!(data.windows(3).any(|w| w == [0xA5, 0x81, 21]));
+        // The third frame has no alpha, and the alpha without colour is dropped.
+        let third = [0xA3, 0x85, 0x81, 0x03, 0x20, 0x00, 12];
+        assert!(data.windows(third.len()).any(|w| w == third));
+        assert!(!data.windows(3).any(|w| w == [0xA5, 0x81, 23]));
+        assert!(data.ends_with(&[0xEE, 0x81, 1, 0xA5, 0x81, 24]));
+    }
+
+    #[test]
+    fn frames_without_alpha_wait_for_the_end() {
+        let (mut webm, mut timeline) = alpha_output(1);
+        let mut pending = PendingFrames::new(true);
+        pending.push(frame(&[1], true, 0));
+        pending.write(&mut webm, &mut timeline, false).unwrap();
+        assert_eq!(pending.frames.len(), 1);
+        pending.write(&mut webm, &mut timeline, true).unwrap();
+        assert!(pending.frames.is_empty());
+    }
+
+    #[test]
+    fn fit_pads_with_the_precision_of_the_image() {
+        // Neighbouring 16 bits grays which fall on two 8 bits values.
+        let gradient = ImageBuffer::from_fn(4, 2, |x, _| {
+            let gray = 32768 + 128 * x as u16;
+            Rgb([gray, gray, gray])
+        });
+        let settings = VideoSettings {
+            bit_depth: 10,
+            ..VideoSettings::default()
+        };
+        let frame = prepare_frame(DynamicImage::ImageRgb16(gradient), 4, 4, &settings);
+        let samples: Vec<u16> = frame
+            .chunks_exact(2)
+            .map(|sample| u16::from_ne_bytes([sample[0], sample[1]]))
+            .collect();
+        assert_eq!(samples.len(), convert::yuv420_size(4, 4));
+        // The image is centered on the second and third rows.
+        assert!(samples[..4].iter().all(|&y| y < 128));
+        for row in [1, 2] {
+            let luma = &samples[4 * row..4 * row + 4];
+            assert!(luma.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", luma);
+        }
+    }
+
+    #[test]
+    fn timeline_mixes_durations() {
+        let mut timeline = Timeline::new(FrameRate::new(30000, 1001).unwrap());
+        timeline.push(0, Some(Duration::from_millis(2500)));
+        timeline.push(1, None);
+        timeline.push(2, None);
+        timeline.push(3, Some(Duration::from_millis(500)));
+        assert_eq!(timeline.timestamp(0), 0);
+        assert_eq!(timeline.timestamp(2), 2_500_000_000 + 33_366_667);
+        assert_eq!(timeline.timestamp(3), 2_500_000_000 + 66_733_333);
+        assert_eq!(timeline.end(), 3_000_000_000 + 66_733_333);
+    }
+
+    fn gray(width: u32, height: u32, value: u8) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_pixel(width, height, Luma([value])))
+    }
+
+    /// Returns the settings of a 25 fps video with the given decode error policy.
+    fn policy_settings(on_decode_error: DecodeErrorPolicy) -> VideoSettings {
+        VideoSettings {
+            fps: FrameRate::new(25, 1).unwrap(),
+            on_decode_error,
+            ..VideoSettings::default()
+        }
+    }
+
+    /// Returns the segment of a WebM file and the timecodes of its blocks.
+    fn segment_blocks(data: &[u8]) -> (Node, Vec<(i64, bool)>) {
+        let mut root = parse(data, 0, data.len());
+        assert_eq!(root.len(), 2);
+        let segment = root.pop().unwrap();
+        let blocks = segment
+            .all(0x1F43_B675)
+            .flat_map(|cluster| block_times(data, cluster))
+            .collect();
+        (segment, blocks)
+    }
+
+    #[test]
+    fn encode_skips_undecodable_frames() {
+        let dir = test_dir("encode-skip");
+        let corrupt = dir.join("corrupt.png");
+        fs::write(&corrupt, b"not a png").unwrap();
+        let frames = [
+            FrameInput::Path(corrupt.clone()),
+            FrameInput::Image(gray(16, 8, 0)),
+            FrameInput::Path(corrupt),
+            FrameInput::Image(gray(32, 32, 128)),
+            FrameInput::Image(gray(16, 8, 255)),
+        ];
+        let data = VideoBuilder::new(Cursor::new(Vec::new()))
+            .settings(policy_settings(DecodeErrorPolicy::Skip))
+            .frames(FrameSource::from_frames(frames.map(Frame::from)))
+            .encode()
+            .unwrap()
+            .into_inner();
+
+        let (segment, blocks) = segment_blocks(&data);
+        // The first frame which can be read gives the size of the video.
+        let video = segment.child(0x1654_AE6B).child(0xAE).child(0xE0);
+        assert_eq!(video.child(0xB0).uint(&data), 16);
+        assert_eq!(video.child(0xBA).uint(&data), 8);
+        // The skipped frames leave no gap in the timeline.
+        let times: Vec<i64> = blocks.iter().map(|(time, _)| *time).collect();
+        assert_eq!(times, [0, 400, 800]);
+        assert!(blocks[0].1);
+
+        // The size, duration and index are patched once the frames are written.
+        let size = &data[segment.start + 4..segment.start + 12];
+        let size = u64::from_be_bytes(size.try_into().unwrap()) & !(1 << 56);
+        assert_eq!(size as usize, data.len() - segment.payload.start);
+        let duration = segment.child(0x1549_A966).child(0x4489).bytes(&data);
+        assert_eq!(f64::from_be_bytes(duration.try_into().unwrap()), 1200.0);
+        let position = |node: &Node| (node.start - segment.payload.start) as u64;
+        let seeks: Vec<(u32, u64)> = segment
+            .child(0x114D_9B74)
+            .all(0x4DBB)
+            .map(|seek| {
+                let id = seek.child(0x53AB).uint(&data) as u32;
+                (id, seek.child(0x53AC).uint(&data))
+            })
+            .collect();
+        assert_eq!(seeks.len(), 3);
+        for (id, seek_position) in seeks {
+            assert_eq!(seek_position, position(segment.child(id)));
+        }
+        let clusters: Vec<u64> = segment.all(0x1F43_B675).map(position).collect();
+        let cues: Vec<(u64, u64)> = segment
+            .child(0x1C53_BB6B)
+            .all(0xBB)
+            .map(|point| {
+                let cluster = point.child(0xB7).child(0xF1).uint(&data);
+                (point.child(0xB3).uint(&data), cluster)
+            })
+            .collect();
+        assert_eq!(cues[0], (0, clusters[0]));
+        assert!(cues.iter().all(|(_, cluster)| clusters.contains(cluster)));
+    }
+
+    #[test]
+    fn encode_live_video() {
+        let frames = (0..3).map(|value| gray(16, 8, value * 100));
+        let data = VideoBuilder::live(Vec::new())
+            .settings(policy_settings(DecodeErrorPolicy::Fail))
+            .frames(FrameSource::from_images(frames))
+            .encode()
+            .unwrap();
+
+        // The file is read from start to end, without a size, duration or index.
+        let (segment, blocks) = segment_blocks(&data);
+        assert_eq!(
+            &data[segment.start + 4..segment.start + 12],
+            [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
+        );
+        let ids = segment.ids();
+        assert_eq!(ids[..2], [0x1549_A966, 0x1654_AE6B]);
+        assert!(ids[2..].iter().all(|&id| id == 0x1F43_B675));
+        assert!(segment.child(0x1549_A966).all(0x4489).next().is_none());
+        let times: Vec<i64> = blocks.iter().map(|(time, _)| *time).collect();
+        assert_eq!(times, [0, 400, 800]);
+        assert!(blocks[0].1);
+    }
+
+    #[test]
+    fn encode_repeats_undecodable_frames() {
+        let dir = test_dir("encode-repeat");
+        let corrupt = dir.join("corrupt.png");
+        fs::write(&corrupt, b"not a png").unwrap();
+        // Without a previous frame, the first frame is skipped.
+        let frames = [
+            FrameInput::Path(corrupt.clone()),
+            FrameInput::Image(gray(16, 8, 0)),
+            FrameInput::Path(corrupt.clone()),
+            FrameInput::Path(corrupt),
+            FrameInput::Image(gray(16, 8, 255)),
+        ];
+        let data = VideoBuilder::new(Cursor::new(Vec::new()))
+            .settings(policy_settings(DecodeErrorPolicy::Repeat))
+            .frames(FrameSource::from_frames(frames.map(Frame::from)))
+            .encode()
+            .unwrap()
+            .into_inner();
+
+        let (_, blocks) = segment_blocks(&data);
+        let times: Vec<i64> = blocks.iter().map(|(time, _)| *time).collect();
+        assert_eq!(times, [0, 400, 800, 1200]);
+    }
+
+    #[test]
+    fn encode_without_decodable_frames() {
+        let dir = test_dir("encode-truncated");
+        // The header of the file can be read, but not its data.
+        let noise = GrayImage::from_fn(16, 8, |x, y| Luma([(x * 37 + y * 91) as u8]));
+        let mut png = Vec::new();
+        DynamicImage::ImageLuma8(noise)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let truncated = dir.join("truncated.png");
+        fs::write(&truncated, &png[..png.len() - 20]).unwrap();
+        let encode = |policy| {
+            VideoBuilder::new(Cursor::new(Vec::new()))
+                .settings(policy_settings(policy))
+                .frames(FrameSource::from_paths(vec![truncated.clone(); 2]))
+                .encode()
+        };
+
+        for policy in [DecodeErrorPolicy::Skip, DecodeErrorPolicy::Repeat] {
+            assert!(matches!(encode(policy), Err(Error::NoImages(_))));
+        }
+        assert!(matches!(
+            encode(DecodeErrorPolicy::Fail),
+            Err(Error::Decode { .. })
+        ));
+    }
+}
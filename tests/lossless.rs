@@ -0,0 +1,182 @@
+//! Lossless VP9 encodings decode back to the exact converted frames.
+
+use std::io::Cursor;
+use std::mem::MaybeUninit;
+use std::{ptr, slice};
+
+use image::{DynamicImage, RgbImage};
+use vpx_sys::*;
+
+use img_to_video::convert::{convert_rgb_to_yuv420, convert_to_yuv444, ColorSpace, Pixels};
+use img_to_video::images::FrameSource;
+use img_to_video::settings::{
+    ChromaFilter, Codec, ColorMatrix, ColorRange, PixelFormat, VideoSettings,
+};
+use img_to_video::VideoBuilder;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+const SEGMENT: u32 = 0x1853_8067;
+const CLUSTER: u32 = 0x1F43_B675;
+const BLOCK_GROUP: u32 = 0xA0;
+const BLOCK: u32 = 0xA1;
+const SIMPLE_BLOCK: u32 = 0xA3;
+
+/// Returns a deterministic pseudo-random RGB image.
+fn noise(seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..WIDTH * HEIGHT * 3)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+/// Encode the images losslessly, in the colour space of [convert_rgb_to_yuv420].
+fn encode(images: &[Vec<u8>], pixel_format: PixelFormat) -> Vec<u8> {
+    let settings = VideoSettings {
+        codec: Codec::Vp9,
+        lossless: true,
+        pixel_format,
+        chroma_filter: ChromaFilter::Box,
+        color_matrix: ColorMatrix::Bt601,
+        color_range: ColorRange::Full,
+        ..VideoSettings::default()
+    };
+    let images: Vec<DynamicImage> = images
+        .iter()
+        .map(|rgb| {
+            RgbImage::from_raw(WIDTH, HEIGHT, rgb.clone())
+                .unwrap()
+                .into()
+        })
+        .collect();
+    VideoBuilder::new(Cursor::new(Vec::new()))
+        .settings(settings)
+        .frames(FrameSource::from_images(images))
+        .encode()
+        .unwrap()
+        .into_inner()
+}
+
+/// Read an EBML variable length integer, keeping its length marker for IDs.
+fn read_vint(data: &[u8], keep_marker: bool) -> (u64, usize) {
+    let length = data[0].leading_zeros() as usize + 1;
+    let mut value = match keep_marker {
+        true => data[0] as u64,
+        false => (data[0] & (0xFF >> length)) as u64,
+    };
+    for &byte in &data[1..length] {
+        value = value << 8 | byte as u64;
+    }
+    (value, length)
+}
+
+/// Collect the frames of the blocks of a WebM file, in file order.
+fn blocks(mut data: &[u8], frames: &mut Vec<Vec<u8>>) {
+    while !data.is_empty() {
+        let (id, id_length) = read_vint(data, true);
+        let (size, size_length) = read_vint(&data[id_length..], false);
+        let header = id_length + size_length;
+        let unknown = size == (1 << (7 * size_length)) - 1;
+        let end = match unknown {
+            true => data.len(),
+            false => header + size as usize,
+        };
+        let payload = &data[header..end];
+        match id as u32 {
+            SEGMENT | CLUSTER | BLOCK_GROUP => blocks(payload, frames),
+            BLOCK | SIMPLE_BLOCK => {
+                // Track number, relative timecode and flags.
+                let (_, track_length) = read_vint(payload, false);
+                frames.push(payload[track_length + 3..].to_vec());
+            }
+            _ => {}
+        }
+        data = &data[end..];
+    }
+}
+
+/// Decode VP9 frames into planes, each plane without padding.
+fn decode(frames: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let mut decoded = Vec::new();
+    unsafe {
+        let mut ctx: vpx_codec_ctx_t = MaybeUninit::zeroed().assume_init();
+        let result = vpx_codec_dec_init_ver(
+            &mut ctx,
+            vpx_codec_vp9_dx(),
+            ptr::null(),
+            0,
+            VPX_DECODER_ABI_VERSION as i32,
+        );
+        assert_eq!(result, VPX_CODEC_OK);
+        for frame in frames {
+            let result = vpx_codec_decode(
+                &mut ctx,
+                frame.as_ptr(),
+                frame.len() as u32,
+                ptr::null_mut(),
+                0,
+            );
+            assert_eq!(result, VPX_CODEC_OK);
+            let mut iter = ptr::null();
+            loop {
+                let image = vpx_codec_get_frame(&mut ctx, &mut iter);
+                if image.is_null() {
+                    break;
+                }
+                decoded.push(planes(&*image));
+            }
+        }
+        vpx_codec_destroy(&mut ctx);
+    }
+    decoded
+}
+
+/// Copy the Y, U and V planes of a decoded 8 bits image.
+unsafe fn planes(image: &vpx_image_t) -> Vec<u8> {
+    assert_eq!((image.d_w, image.d_h), (WIDTH, HEIGHT));
+    let mut yuv = Vec::new();
+    for plane in 0..3 {
+        let (x_shift, y_shift) = match plane {
+            0 => (0, 0),
+            _ => (image.x_chroma_shift, image.y_chroma_shift),
+        };
+        let width = ((image.d_w + (1 << x_shift) - 1) >> x_shift) as usize;
+        let height = ((image.d_h + (1 << y_shift) - 1) >> y_shift) as usize;
+        let stride = image.stride[plane] as usize;
+        let data = slice::from_raw_parts(image.planes[plane], stride * height);
+        for row in data.chunks(stride) {
+            yuv.extend_from_slice(&row[..width]);
+        }
+    }
+    yuv
+}
+
+fn round_trip(pixel_format: PixelFormat, convert: impl Fn(&[u8]) -> Vec<u8>) {
+    let images: Vec<Vec<u8>> = (1..=3).map(noise).collect();
+    let webm = encode(&images, pixel_format);
+    let mut frames = Vec::new();
+    blocks(&webm, &mut frames);
+    let decoded = decode(&frames);
+    assert_eq!(decoded.len(), images.len());
+    for (index, (rgb, yuv)) in images.iter().zip(&decoded).enumerate() {
+        assert!(convert(rgb) == *yuv, "frame {} differs", index);
+    }
+}
+
+#[test]
+fn lossless_yuv420() {
+    round_trip(PixelFormat::I420, |rgb| {
+        convert_rgb_to_yuv420(WIDTH, HEIGHT, rgb, 3)
+    });
+}
+
+#[test]
+fn lossless_yuv444() {
+    round_trip(PixelFormat::I444, |rgb| {
+        convert_to_yuv444(WIDTH, HEIGHT, Pixels::Rgb8(rgb), ColorSpace::default())
+    });
+}